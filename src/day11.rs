@@ -1,87 +1,120 @@
 use utils;
 use std::collections::HashMap;
+use std::hash::Hash;
+use std::ops::{Div, Rem, Sub};
+use std::str::FromStr;
 
 
-fn parse_input(txt: &str) -> Vec<u64> {
+// stone values, u64 is enough for the puzzle but u128 gives room on bigger inputs
+trait Stone: Copy + Eq + Hash + FromStr + From<u32> + Div<Output = Self> + Rem<Output = Self> + Sub<Output = Self> {
+    fn checked_mul(self, rhs: Self) -> Option<Self>;
+}
+
+impl Stone for u64 {
+    fn checked_mul(self, rhs: Self) -> Option<Self> {
+        u64::checked_mul(self, rhs)
+    }
+}
+
+impl Stone for u128 {
+    fn checked_mul(self, rhs: Self) -> Option<Self> {
+        u128::checked_mul(self, rhs)
+    }
+}
+
+fn parse_input<T: Stone>(txt: &str) -> Vec<T> {
     txt.split_whitespace()
     .map(|word| {
-        word.parse::<u64>().unwrap()
+        match word.parse::<T>() {
+            Ok(num) => num,
+            Err(_) => panic!("Invalid stone: {}", word)
+        }
     }).collect()
 }
 
-enum Either {
-    NUM(u64),
-    SPLIT(u64, u64),
+#[derive(Debug, PartialEq)]
+enum Either<T> {
+    NUM(T),
+    SPLIT(T, T),
 }
 
-fn apply_rule(num: u64) -> Either {
-    if num == 0 {
-        return Either::NUM(1);
+// None if the new value doesn't fit in T
+fn apply_rule<T: Stone>(num: T) -> Option<Either<T>> {
+    if num == T::from(0) {
+        return Some(Either::NUM(T::from(1)));
     } else {
         let digits = count_digits(num);
         if digits % 2 == 0 {
         let (l, r) = split_number(num, digits);
-            return Either::SPLIT(l, r);
+            return Some(Either::SPLIT(l, r));
         } else {
-            return Either::NUM(num*2024);
+            return num.checked_mul(T::from(2024)).map(Either::NUM);
         }
     }
 }
 
-fn count_digits(num: u64) -> u64 {
-    let mut base = 10;
+fn count_digits<T: Stone>(num: T) -> u32 {
+    let ten = T::from(10);
+    let mut rest = num / ten;
     let mut digits = 1;
-    while num/base != 0 {
-        base *= 10;
+    while rest != T::from(0) {
+        rest = rest / ten;
         digits += 1;
     }
     return digits;
 }
 
-fn split_number(num: u64, digits: u64) -> (u64, u64) {
-    let midpoint_base: u64 = 10_u64.pow(digits as u32/2);
+fn split_number<T: Stone>(num: T, digits: u32) -> (T, T) {
+    // 10^(digits/2) always fits since num has `digits` digits
+    let mut midpoint_base = T::from(1);
+    for _ in 0..digits/2 {
+        midpoint_base = midpoint_base.checked_mul(T::from(10)).unwrap();
+    }
     let right = num % midpoint_base;
     let left = (num-right)/midpoint_base;
     return (left, right);
 }
 
-fn blink_transform(list: &[u64]) -> Vec<u64> {
-    list.into_iter()
-    .map( |&num| {
-        match apply_rule(num) {
-            Either::NUM(c) => vec![c],
-            Either::SPLIT(l, r) => vec![l, r]
+fn blink_transform<T: Stone>(list: &[T]) -> Option<Vec<T>> {
+    let mut ret = Vec::with_capacity(list.len());
+    for &num in list {
+        match apply_rule(num)? {
+            Either::NUM(c) => ret.push(c),
+            Either::SPLIT(l, r) => {
+                ret.push(l);
+                ret.push(r);
+            }
         }
-    }).flatten()
-    .collect()
+    }
+    Some(ret)
 }
 
 // unused recursive solution without cache
 // try to memoize this one
-fn _blink_r_helper(num: u64, round: usize, target_round: usize) -> usize {
+fn _blink_r_helper<T: Stone>(num: T, round: usize, target_round: usize) -> Option<usize> {
     if round >= target_round {
-        return 1;
+        return Some(1);
     }
-    return match apply_rule(num) {
+    return match apply_rule(num)? {
         Either::NUM(c) => _blink_r_helper(c, round+1, target_round),
-        Either::SPLIT(l,r ) => _blink_r_helper(l, round+1, target_round) + _blink_r_helper(r, round+1, target_round)
+        Either::SPLIT(l,r ) => Some(_blink_r_helper(l, round+1, target_round)? + _blink_r_helper(r, round+1, target_round)?)
     }
 }
 
-fn blink_r_helper_with_cache(num: u64, round: usize, target_round: usize, cache: &mut HashMap<(u64, usize), usize>) -> usize {
+fn blink_r_helper_with_cache<T: Stone>(num: T, round: usize, target_round: usize, cache: &mut HashMap<(T, usize), usize>) -> Option<usize> {
     if let Some(&res) = cache.get(&(num, round)) {
-        return res;
+        return Some(res);
     } else {
         if round >= target_round {
             cache.insert((num, round), 1);
-            return 1;
+            return Some(1);
         } else {
-            let res = match apply_rule(num) {
-                Either::NUM(c) => blink_r_helper_with_cache(c, round+1, target_round, cache),
-                Either::SPLIT(l,r) => blink_r_helper_with_cache(l, round+1, target_round, cache) + blink_r_helper_with_cache(r, round+1, target_round, cache)
+            let res = match apply_rule(num)? {
+                Either::NUM(c) => blink_r_helper_with_cache(c, round+1, target_round, cache)?,
+                Either::SPLIT(l,r) => blink_r_helper_with_cache(l, round+1, target_round, cache)? + blink_r_helper_with_cache(r, round+1, target_round, cache)?
             };
             cache.insert((num, round), res);
-            return res;
+            return Some(res);
         }
     }
 }
@@ -89,67 +122,94 @@ fn blink_r_helper_with_cache(num: u64, round: usize, target_round: usize, cache:
 
 
 
-fn solve_p1(pb: &[u64], blink_times: u32) -> usize {
+// None if a stone outgrows T
+fn solve_p1<T: Stone>(pb: &[T], blink_times: u32) -> Option<usize> {
     let mut input = Vec::from(pb);
     for _ in 0..blink_times {
-        input = blink_transform(&input)
+        input = blink_transform(&input)?
     }
-    return input.len()
+    return Some(input.len())
 }
 
-fn solve_p2(pb: &[u64], blink_times: u32) -> usize {
+// None if a stone outgrows T
+fn solve_p2<T: Stone>(pb: &[T], blink_times: u32) -> Option<usize> {
     let mut sum: usize = 0;
-    let mut cache: HashMap<(u64, usize), usize> = HashMap::new();
+    let mut cache: HashMap<(T, usize), usize> = HashMap::new();
     for a in pb {
         //sum += blink_r_helper(*a, 0, blink_times as usize);
-        sum += blink_r_helper_with_cache(*a, 0, blink_times as usize, &mut cache);
+        sum += blink_r_helper_with_cache(*a, 0, blink_times as usize, &mut cache)?;
     }
-    return sum;
+    return Some(sum);
 }
 
 pub fn day11() {
     let text = utils::read_file_as_text("inputs/day11.txt");
-    let pb = parse_input(&text);
-    let soln = solve_p1(&pb, 25);
+    let pb = parse_input::<u64>(&text);
+    let soln = solve_p1(&pb, 25).expect("Stone values overflowed u64");
     println!("Solution to day 11 part 1: {}", soln); // 189167
-    let soln2 = solve_p2(&pb, 75);
+    // fall back to u128 stones if u64 isn't enough
+    let soln2 = solve_p2(&pb, 75)
+        .or_else(|| solve_p2(&parse_input::<u128>(&text), 75))
+        .expect("Stone values overflowed u128");
     println!("Solution to day 11 part 2: {}", soln2); // 225253278506288
 }
 
 #[cfg(test)]
 mod test {
-    use super::{blink_transform, count_digits, parse_input, solve_p1, solve_p2, split_number};
+    use super::{apply_rule, blink_transform, count_digits, parse_input, solve_p1, solve_p2, split_number, Either};
 
     #[test]
     fn test_digits_count() {
-        assert_eq!(count_digits(100), 3);
-        assert_eq!(count_digits(0), 1);
-        assert_eq!(count_digits(99), 2);
-        assert_eq!(count_digits(10), 2);
+        assert_eq!(count_digits(100_u64), 3);
+        assert_eq!(count_digits(0_u64), 1);
+        assert_eq!(count_digits(99_u64), 2);
+        assert_eq!(count_digits(10_u64), 2);
+        assert_eq!(count_digits(u64::MAX), 20);
+        assert_eq!(count_digits(u128::MAX), 39);
     }
 
     #[test]
     fn test_split_digits() {
-        let (mut left, mut right) = split_number(1234, 4);
+        let (mut left, mut right) = split_number(1234_u64, 4);
         assert_eq!(left, 12);
         assert_eq!(right, 34);
         (left, right) = split_number(10, 2);
         assert_eq!(left, 1);
         assert_eq!(right, 0);
+        assert_eq!(split_number(u64::MAX, 20), (1844674407, 3709551615));
     }
+
     #[test]
     fn test_one_blink() {
         let text = r"0 1 10 99 999";
-        let pb = parse_input(&text);
+        let pb = parse_input::<u64>(&text);
         let output = blink_transform(&pb);
-        assert_eq!(output, vec![1,2024,1,0,9,9,2021976])
+        assert_eq!(output, Some(vec![1,2024,1,0,9,9,2021976]))
     }
 
     #[test]
     fn test_example() {
         let text = r"125 17";
-        let pb = parse_input(&text);
-        assert_eq!(solve_p1(&pb, 25), 55312);
-        assert_eq!(solve_p2(&pb, 25), 55312);
+        let pb = parse_input::<u64>(&text);
+        assert_eq!(solve_p1(&pb, 25), Some(55312));
+        assert_eq!(solve_p2(&pb, 25), Some(55312));
+        let pb = parse_input::<u128>(&text);
+        assert_eq!(solve_p2(&pb, 25), Some(55312));
+    }
+
+    #[test]
+    fn test_overflow() {
+        // 19 digits, times 2024 doesn't fit in a u64
+        let big: u64 = 1_000_000_000_000_000_000;
+        assert_eq!(apply_rule(big), None);
+        assert_eq!(apply_rule(big as u128), Some(Either::NUM(2_024_000_000_000_000_000_000)));
+        // every 17 digit number overflows when multiplied, the largest odd-digit ones that fit have 15 digits
+        assert_eq!(apply_rule(10_000_000_000_000_000_u64), None);
+        assert_eq!(apply_rule(999_999_999_999_999_u64), Some(Either::NUM(999_999_999_999_999 * 2024)));
+        assert_eq!(solve_p1(&[big], 3), None);
+        assert_eq!(solve_p2(&[big], 3), None);
+        // 2024000000000000000000 -> 20240000000 0 -> 40965760000000 1
+        assert_eq!(solve_p1(&[big as u128], 3), Some(2));
+        assert_eq!(solve_p2(&[big as u128], 3), Some(2));
     }
 }
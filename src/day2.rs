@@ -9,8 +9,11 @@ pub fn day2() {
 8 6 4 4 1
 1 3 6 7 9";*/
     let reports = utils::parse_line_by_line(&input);
-    let ret = utils::check_safety(&reports);
-    println!("number of safe reports: {}", ret);
-    let ret2 = utils::check_safety_p2(&reports);
-    println!("number of safe reports with dampener: {}", ret2);
+    let rules = utils::SafetyRules::default();
+    let (_, summary) = utils::check_reports(&reports, &rules, 0);
+    println!("number of safe reports: {}", summary.safe);
+    let (_, summary2) = utils::check_reports(&reports, &rules, 1);
+    println!("number of safe reports with dampener: {}", summary2.safe);
+    println!("unsafe even with dampener: {} (direction: {}, step: {}, equal: {})",
+        summary2.direction + summary2.step + summary2.equal, summary2.direction, summary2.step, summary2.equal);
 }
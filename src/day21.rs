@@ -1,6 +1,6 @@
 use std::fs;
 use std::collections::BinaryHeap;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::cmp::Ordering;
 
 // straight from the BinaryHeap docs example
@@ -88,42 +88,130 @@ pub fn a_star_best_paths(start: &(i32, i32), goal: &(i32, i32),
 }
 
 
-struct Puzzle {
-    numpad_board: Vec<Vec<char>>,
-    keypad_board: Vec<Vec<char>>,
-    codes: Vec<String>
+const NUMPAD_LAYOUT: &str = r"
+789
+456
+123
+#0A
+";
+
+const KEYPAD_LAYOUT: &str = r"
+#^A
+<v>
+";
+
+// a keypad layout, one char per key and `gaps` where there is no key to hover over
+#[derive(Debug, Clone)]
+struct Keypad {
+    board: Vec<Vec<char>>,
+    gaps: HashSet<(i32, i32)>,
+    positions: HashMap<char, (i32, i32)>,
 }
 
-fn get_keypad_position(input: char) -> (i32, i32) {
-    match input {
-        ' ' => (0, 0),
-        '^' => (1, 0),
-        'A' => (2, 0),
-        '<' => (0, 1),
-        'v' => (1, 1),
-        '>' => (2, 1),
-        _ => panic!("Invalid input: {}", input)
+impl Keypad {
+    // only the blank lines around the layout are dropped, the gap marker may be a space.
+    // every key must be reachable from 'A' without going over the gap
+    fn parse(text: &str, gap_marker: char) -> Result<Self, String> {
+        let lines: Vec<&str> = text.split("\n").map(|line| line.trim_end_matches('\r')).collect();
+        let first = lines.iter().position(|line| !line.is_empty()).ok_or("Empty keypad layout")?;
+        let last = lines.iter().rposition(|line| !line.is_empty()).unwrap();
+        let board: Vec<Vec<char>> = lines[first..=last].iter().map(|line| line.chars().collect()).collect();
+        let mut gaps = HashSet::new();
+        let mut positions = HashMap::new();
+        for (i, row) in board.iter().enumerate() {
+            if row.len() != board[0].len() {
+                return Err("Keypad rows must all have the same width".to_string());
+            }
+            for (j, &key) in row.iter().enumerate() {
+                let pos = (j as i32, i as i32);
+                if key == gap_marker {
+                    gaps.insert(pos);
+                } else if positions.insert(key, pos).is_some() {
+                    return Err(format!("Duplicate key {} in keypad", key));
+                }
+            }
+        }
+        let keypad = Keypad {
+            board,
+            gaps,
+            positions
+        };
+        let start = *keypad.positions.get(&'A').ok_or("Keypad has no A key")?;
+        let mut reached = HashSet::from([start]);
+        let mut queue = VecDeque::from([start]);
+        while let Some(current) = queue.pop_front() {
+            for next in keypad.next_positions(&current) {
+                if reached.insert(next) {
+                    queue.push_back(next);
+                }
+            }
+        }
+        if let Some((key, _)) = keypad.positions.iter().find(|(_, pos)| !reached.contains(*pos)) {
+            return Err(format!("Key {} cannot be reached without going over the gap", key));
+        }
+        Ok(keypad)
+    }
+
+    fn rows(&self) -> i32 {
+        self.board.len() as i32
     }
-}
 
-fn get_numpad_position(input: char) -> (i32, i32) {
-    match input {
-        ' ' => (0, 3),
-        '0' => (1, 3),
-        'A' => (2, 3),
-        '1' => (0, 2),
-        '2' => (1, 2),
-        '3' => (2, 2),
-        '4' => (0, 1),
-        '5' => (1, 1),
-        '6' => (2, 1),
-        '7' => (0, 0),
-        '8' => (1, 0),
-        '9' => (2, 0),
-        _ => panic!("Oops, invalid character {} requested", input)
+    fn cols(&self) -> i32 {
+        self.board[0].len() as i32
+    }
+
+    fn position(&self, key: char) -> (i32, i32) {
+        match self.positions.get(&key) {
+            Some(&pos) => pos,
+            None => panic!("Oops, invalid character {} requested", key)
+        }
+    }
+
+    // moves an arm starting on 'A' according to the presses and returns the keys pressed
+    fn replay(&self, presses: &str) -> Result<String, String> {
+        let mut pos = self.position('A');
+        let mut pressed = String::new();
+        for (idx, press) in presses.chars().enumerate() {
+            let (dx, dy) = match press {
+                'A' => {
+                    pressed.push(self.board[pos.1 as usize][pos.0 as usize]);
+                    continue;
+                },
+                '>' => (1, 0),
+                '<' => (-1, 0),
+                'v' => (0, 1),
+                '^' => (0, -1),
+                _ => return Err(format!("invalid press {} at {}", press, idx))
+            };
+            pos = (pos.0 + dx, pos.1 + dy);
+            if pos.0 < 0 || pos.0 >= self.cols() || pos.1 < 0 || pos.1 >= self.rows() {
+                return Err(format!("arm left the keypad at {}", idx));
+            }
+            if self.gaps.contains(&pos) {
+                return Err(format!("arm entered the gap at {}", idx));
+            }
+        }
+        Ok(pressed)
+    }
+
+    fn next_positions(&self, current: &(i32, i32)) -> Vec<(i32, i32)> {
+        // 4 directions
+        [(-1, 0), (0, -1), (0, 1), (1, 0)].into_iter()
+            .map( |(dx, dy)| (current.0 + dx, current.1 + dy))
+            .filter(|(j, i)| *i >= 0 && *i < self.rows() && *j >= 0 && *j < self.cols())
+            .filter(|candidate| !self.gaps.contains(candidate)) // robots must never hover over the gap
+            .collect()
     }
 }
 
+struct Puzzle {
+    numpad: Keypad,
+    keypad: Keypad,
+    // number of directional keypads operated by robots between the human and the numpad robot
+    robot_layers: u32,
+    codes: Vec<String>
+}
+
 fn get_direction_char(p: &(i32, i32), n: &(i32, i32)) -> char {
     let dxdy = (n.0 - p.0, n.1 - p.1);
     match dxdy {
@@ -147,85 +235,58 @@ fn deduce_directions(positions: &[(i32, i32)]) -> Vec<char> {
 }
 
 fn parse_puzzle(text: &str) -> Puzzle {
-    let numpad_board = vec![
-        vec!['7', '8', '9'],
-        vec!['4', '5', '6'],
-        vec!['1', '2', '3'],
-        vec![' ', '0', 'A']
-    ];
-    let keypad_board = vec![
-        vec![' ', '^', 'A'],
-        vec!['<', 'v', '>']
-    ];
+    parse_puzzle_with(text, Keypad::parse(NUMPAD_LAYOUT, '#').unwrap(), Keypad::parse(KEYPAD_LAYOUT, '#').unwrap(), 2)
+}
+
+fn parse_puzzle_with(text: &str, numpad: Keypad, keypad: Keypad, robot_layers: u32) -> Puzzle {
     let codes = text.trim().split("\n").map(|line| line.trim().to_string() ).collect();
     Puzzle {
-        numpad_board,
-        keypad_board,
+        numpad,
+        keypad,
+        robot_layers,
         codes
     }
 }
 
 impl Puzzle {
-    fn next_positions_numpad(&self, current: &(i32, i32)) -> Vec<(i32, i32)> {
-        // 4 directions
-        [(-1, 0), (0, -1), (0, 1), (1, 0)].into_iter()
-            .map( |(dx, dy)| (current.0 + dx, current.1 + dy))
-            .filter(|(j, i)| *i >= 0 && *i < 4 && *j >= 0 && *j < 3)
-            .filter( |candidate| *candidate != (0, 3)) // avoid space
-            .collect()
-    }
-
-    fn next_positions_keypad(&self, current: &(i32, i32)) -> Vec<(i32, i32)> {
-        // 4 directions
-        [(-1, 0), (0, -1), (0, 1), (1, 0)].into_iter()
-            .map( |(dx, dy)| (current.0 + dx, current.1 + dy))
-            .filter(|(j, i)| *i >= 0 && *i < 2 && *j >= 0 && *j < 3)
-            .filter(|candidate| *candidate != (0, 0)) // should never have to go here
-            .collect()
+    // layer 0 -> numpad, the rest use keypad
+    fn pad(&self, layer: u32) -> &Keypad {
+        match layer {
+            0 => &self.numpad,
+            _ => &self.keypad
+        }
     }
 
     fn cost_fn(&self, _current: &(i32, i32)) -> usize {
         1
     }
 
+    // all the shortest ways to go from one key to the other and press it
+    fn moves_between(&self, from: char, to: char, layer: u32) -> Vec<String> {
+        let pad = self.pad(layer);
+        let start = pad.position(from);
+        let end = pad.position(to);
+        let best_paths  = a_star_best_paths(
+            &start, &end,
+            &|arg| self.cost_fn(arg),
+            &|state| manhattan_distance(state, &end),
+            &|arg| pad.next_positions(arg),
+            START_CHAR
+        );
+        best_paths.into_iter().map(|(_cost, path)| {
+            let mut directions = deduce_directions(&path.into_iter().map(|(x, y, _)| (x, y)).collect::<Vec<_>>());
+            directions.push('A');
+            directions.into_iter().collect()
+        }).collect()
+    }
+
     // layer 0 -> numpad, the rest use keypad, returns command sequence as string (cost is simply the length)
     pub fn get_moves_per_path(&self, pattern: &str, layer: u32) -> Vec<String> {
         let mut prev = 'A';
-        let mut sum_moves: Vec<usize> = Vec::new();
         let mut moves: Vec<Vec<String>> = vec![vec![];pattern.len()];
-        let mut start_dir = START_CHAR; //if layer == 0 { '<' } else { START_CHAR };
-        let next_pos: &dyn Fn(&(i32, i32)) -> Vec<(i32, i32)> = match layer {
-            0 => &|arg| { self.next_positions_numpad(arg)},
-            _ => &|arg| {self.next_positions_keypad(arg)}
-        };
         for (slot_id, current) in pattern.chars().enumerate() {
-            let start = if layer == 0 { get_numpad_position(prev) } else { get_keypad_position(prev) };
-            let end = if layer == 0 { get_numpad_position(current) } else { get_keypad_position(current) };
-            //print!("Go from {} to {}: ", prev, current);
+            moves[slot_id] = self.moves_between(prev, current, layer);
             prev = current;
-            let best_paths  = a_star_best_paths(
-                &start, &end,
-                &|arg| self.cost_fn(arg),
-                &|state| manhattan_distance(state, &end),
-                next_pos,
-                start_dir
-            );
-            for (_cost, path) in best_paths {
-                let mut possible_ways = moves.get_mut(slot_id).unwrap();
-                if path.len() > 1 { // have to move
-                    start_dir = path.last().unwrap().2;
-                    let mut directions = deduce_directions(&path.into_iter().map(|(x, y, _)| (x, y)).collect::<Vec<_>>());
-                    directions.push('A');
-                    let actual_cost = manhattan_distance(&start, &end);
-                    //sum_moves += actual_cost + 1;
-                    //moves = [moves.clone(), directions].concat();
-                    possible_ways.push(directions.into_iter().collect());
-                } else { // don't have to move
-                    //moves = [moves.clone(), ['A'].to_vec()].concat();
-                    //sum_moves += 1;
-                    possible_ways.push("A".to_string());
-                }
-            }
         }
         // build all the paths into a single vec
         let mut total_possibilites = 1;
@@ -250,28 +311,92 @@ impl Puzzle {
 
     }
 
+    // shortest expansion of `path` through the remaining robot layers, by trying every best move
+    fn best_expansion(&self, path: &str, layer: u32) -> String {
+        if layer > self.robot_layers {
+            return path.to_string();
+        }
+        self.get_moves_per_path(path, layer).iter()
+            .map(|next_path| self.best_expansion(next_path, layer+1))
+            .min_by_key(|expanded| expanded.len())
+            .unwrap()
+    }
+
     pub fn compute_pt1_score(&self, pattern: &str) -> usize {
-        let mut best_score = usize::MAX;
-        let mut best_path: String = "".to_string();
-        for l1path in self.get_moves_per_path(pattern, 0) {
-            for l2path in self.get_moves_per_path(&l1path, 1) {
-                for l3path in self.get_moves_per_path(&l2path, 2) {
-                    if l3path.len() < best_score {
-                        best_score = l3path.len();
-                        best_path = l3path.clone();
-                    }
-                }
-            }
+        let best_path = self.best_expansion(pattern, 0);
+        let numerical_val = pattern.chars().filter(|digit| digit.is_ascii_digit()).collect::<String>().parse::<usize>().unwrap();
+        best_path.len()*numerical_val
+    }
+
+    // number of presses the human needs so that the robot at `layer` goes from `from` to `to` and presses it
+    fn press_cost(&self, from: char, to: char, layer: u32, cache: &mut HashMap<(char, char, u32), u64>) -> u64 {
+        if layer > self.robot_layers {
+            // the human presses the button directly
+            return 1;
+        }
+        if let Some(&cost) = cache.get(&(from, to, layer)) {
+            return cost;
+        }
+        let cost = self.moves_between(from, to, layer).iter()
+            .map(|moves| self.sequence_cost(moves, layer+1, cache))
+            .min()
+            .unwrap();
+        cache.insert((from, to, layer), cost);
+        cost
+    }
+
+    // every layer starts (and ends, after pressing) on 'A'
+    fn sequence_cost(&self, sequence: &str, layer: u32, cache: &mut HashMap<(char, char, u32), u64>) -> u64 {
+        let mut prev = 'A';
+        let mut cost = 0;
+        for current in sequence.chars() {
+            cost += self.press_cost(prev, current, layer, cache);
+            prev = current;
         }
-        println!("Best path for pattern {}: {}", pattern, best_path);
-        let numerical_val = pattern.chars().filter(|digit| digit.is_digit(10)).collect::<String>().parse::<usize>().unwrap();
+        cost
+    }
+
+    pub fn compute_pt2_score(&self, pattern: &str) -> u64 {
+        let mut cache = HashMap::new();
+        let best_score = self.sequence_cost(pattern, 0, &mut cache);
+        let numerical_val = pattern.chars().filter(|digit| digit.is_ascii_digit()).collect::<String>().parse::<u64>().unwrap();
         best_score*numerical_val
     }
 
-    pub fn compute_pt2_score(&self, pattern: &str, layer_num: usize, target_layer: usize) -> u64 {
-        // use recursion + cache for part 2!
-        // TODO
-        0
+    // picks the cheapest moves for every key of `sequence` (typed on the pad at `layer`)
+    fn expand_sequence(&self, sequence: &str, layer: u32, cache: &mut HashMap<(char, char, u32), u64>) -> String {
+        let mut prev = 'A';
+        let mut ret = String::new();
+        for current in sequence.chars() {
+            let best = self.moves_between(prev, current, layer).into_iter()
+                .min_by_key(|moves| self.sequence_cost(moves, layer+1, cache))
+                .unwrap();
+            ret.push_str(&best);
+            prev = current;
+        }
+        ret
+    }
+
+    // returns the code followed by what has to be typed on every layer, the last one being the human presses.
+    // the length of each layer grows ~2.5x so only use this for a handful of robot layers
+    pub fn best_sequences(&self, pattern: &str) -> Vec<String> {
+        let mut cache = HashMap::new();
+        let mut layers = vec![pattern.to_string()];
+        for layer in 0..=self.robot_layers {
+            let expanded = self.expand_sequence(layers.last().unwrap(), layer, &mut cache);
+            layers.push(expanded);
+        }
+        layers
+    }
+
+    // replays the human presses through the whole chain and returns what ends up typed on the numpad
+    pub fn simulate(&self, presses: &str) -> Result<String, String> {
+        let mut typed = presses.to_string();
+        for layer in (0..=self.robot_layers).rev() {
+            typed = self.pad(layer).replay(&typed)
+                .map_err(|err| format!("layer {}: {}", layer, err))?;
+        }
+        Ok(typed)
     }
 
 }
@@ -284,22 +409,30 @@ fn solve_pt1(pb: &Puzzle) -> usize {
 
 fn solve_pt2(pb: &Puzzle) -> u64 {
     pb.codes.iter().map(
-        |pattern| pb.compute_pt2_score(pattern, 0, 25)
+        |pattern| pb.compute_pt2_score(pattern)
     ).sum::<u64>()
 }
 
 pub fn day21() {
     let text = fs::read_to_string("inputs/day21.txt").unwrap();
-    let puzzle = parse_puzzle(&text);
+    let mut puzzle = parse_puzzle(&text);
+    for code in &puzzle.codes {
+        let presses = puzzle.best_sequences(code).pop().unwrap();
+        // replaying the presses through the robots must type the code back
+        assert_eq!(puzzle.simulate(&presses).as_ref(), Ok(code));
+        println!("Best path for pattern {}: {}", code, presses);
+    }
     let soln = solve_pt1(&puzzle);
     println!("Solution to day 21 part 1: {}", soln); // 94426
+    puzzle.robot_layers = 25;
     let soln2 = solve_pt2(&puzzle);
     println!("Solution to day 21 part 2: {}", soln2);
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{parse_puzzle, solve_pt1};
+    use std::collections::HashSet;
+    use super::{parse_puzzle, parse_puzzle_with, solve_pt1, solve_pt2, Keypad, Puzzle, KEYPAD_LAYOUT, NUMPAD_LAYOUT};
 
     const SAMPLE: &str = r"
 029A
@@ -309,6 +442,12 @@ mod tests {
 379A
 ";
 
+    fn puzzle_with_layers(robot_layers: u32) -> Puzzle {
+        let numpad = Keypad::parse(NUMPAD_LAYOUT, '#').unwrap();
+        let keypad = Keypad::parse(KEYPAD_LAYOUT, '#').unwrap();
+        parse_puzzle_with(&SAMPLE, numpad, keypad, robot_layers)
+    }
+
     #[test]
     fn test_029A() {
         let pb = parse_puzzle(&SAMPLE);
@@ -379,4 +518,101 @@ mod tests {
         let pb = parse_puzzle(&SAMPLE);
         assert_eq!(solve_pt1(&pb), 126384);
     }
+
+    #[test]
+    fn test_keypad_layout() {
+        let numpad = Keypad::parse(NUMPAD_LAYOUT, '#').unwrap();
+        assert_eq!(numpad.position('7'), (0, 0));
+        assert_eq!(numpad.position('A'), (2, 3));
+        assert_eq!(numpad.gaps, HashSet::from([(0, 3)]));
+        // from '1' we can go up or right but never down into the gap
+        assert_eq!(numpad.next_positions(&(0, 2)), vec![(0, 1), (1, 2)]);
+        let keypad = Keypad::parse(KEYPAD_LAYOUT, '#').unwrap();
+        assert_eq!(keypad.position('<'), (0, 1));
+        assert_eq!(keypad.gaps, HashSet::from([(0, 0)]));
+        // the original boards used a space for the gap
+        let spaced = Keypad::parse("\n ^A\n<v>\n", ' ').unwrap();
+        assert_eq!(spaced.gaps, HashSet::from([(0, 0)]));
+        assert_eq!(spaced.position('^'), (1, 0));
+        assert!(Keypad::parse("12\n3", '#').is_err());
+        assert!(Keypad::parse("1A1", '#').is_err());
+        assert!(Keypad::parse("123", '#').is_err());
+    }
+
+    #[test]
+    fn test_robot_layers() {
+        // human typing directly on the numpad
+        let pb = puzzle_with_layers(0);
+        assert_eq!(pb.compute_pt1_score("029A"), "<A^A>^^AvvvA".len()*29);
+        assert_eq!(pb.compute_pt2_score("029A"), 12*29);
+        // a single robot in between
+        let pb = puzzle_with_layers(1);
+        assert_eq!(pb.compute_pt1_score("029A"), "v<<A>>^A<A>AvA<^AA>A<vAAA>^A".len()*29);
+        assert_eq!(pb.compute_pt2_score("029A"), 28*29);
+    }
+
+    #[test]
+    fn test_custom_keypad() {
+        // a square numpad with the gap in the top right corner
+        let numpad = Keypad::parse("1#\n3A", '#').unwrap();
+        let keypad = Keypad::parse(KEYPAD_LAYOUT, '#').unwrap();
+        let pb = parse_puzzle_with("3A\n1A", numpad, keypad, 0);
+        assert_eq!(pb.compute_pt1_score("3A"), "<A>A".len()*3);
+        assert_eq!(pb.compute_pt2_score("1A"), "<^Av>A".len() as u64);
+        // a single row with the gap in the middle leaves '1' and '2' out of reach
+        let err = Keypad::parse("12#3A", '#').unwrap_err();
+        assert!(err.contains("cannot be reached"));
+        // every gap marker is a gap, not only the last one
+        let holes = Keypad::parse("#1#\n2A3", '#').unwrap();
+        assert_eq!(holes.gaps, HashSet::from([(0, 0), (2, 0)]));
+        assert_eq!(holes.next_positions(&holes.position('1')), vec![(1, 1)]);
+        assert!(holes.replay("^<A").unwrap_err().contains("gap"));
+        assert!(holes.replay("^>A").unwrap_err().contains("gap"));
+        assert_eq!(holes.replay("^Av<A"), Ok("12".to_string()));
+    }
+
+    #[test]
+    fn test_best_sequences() {
+        let pb = parse_puzzle(&SAMPLE);
+        for pattern in &pb.codes {
+            let layers = pb.best_sequences(pattern);
+            assert_eq!(layers.len(), 4);
+            assert_eq!(layers.first().unwrap(), pattern);
+            let presses = layers.last().unwrap();
+            assert_eq!(presses.len() as u64 * pattern[..3].parse::<u64>().unwrap(), pb.compute_pt2_score(pattern));
+            assert_eq!(pb.simulate(presses), Ok(pattern.clone()));
+            // every layer types the one above it
+            for layer in 0..=2 {
+                let typed = pb.pad(layer).replay(&layers[layer as usize + 1]);
+                assert_eq!(typed.as_ref(), Ok(&layers[layer as usize]));
+            }
+        }
+    }
+
+    #[test]
+    fn test_simulate() {
+        let pb = parse_puzzle(&SAMPLE);
+        let presses = "<vA<AA>>^AvAA<^A>A<v<A>>^AvA^A<vA>^A<v<A>^A>AAvA^A<v<A>A>^AAAvA<^A>A";
+        assert_eq!(pb.simulate(presses), Ok("029A".to_string()));
+        // going left twice from 'A' on the numpad lands on the gap
+        let pb = puzzle_with_layers(0);
+        assert!(pb.simulate("<<A").unwrap_err().contains("gap"));
+        assert!(pb.simulate("^A").is_ok());
+        assert!(pb.simulate("vA").unwrap_err().contains("left the keypad"));
+    }
+
+    #[test]
+    fn test_pt2_matches_pt1_at_depth_2() {
+        let pb = parse_puzzle(&SAMPLE);
+        // lengths of the human presses listed above
+        let lengths = [("029A", 68), ("980A", 60), ("179A", 68), ("456A", 64), ("379A", 64)];
+        for (pattern, length) in lengths {
+            assert_eq!(pb.compute_pt2_score(pattern), length * pattern[..3].parse::<u64>().unwrap());
+        }
+        // the enumeration takes seconds per code, one is enough to cross-check
+        assert_eq!(pb.compute_pt2_score("029A"), pb.compute_pt1_score("029A") as u64);
+        // 2 robot layers and 25 robot layers
+        assert_eq!(solve_pt2(&pb), 126384);
+        assert_eq!(solve_pt2(&puzzle_with_layers(25)), 154115708116294);
+    }
 }
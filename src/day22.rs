@@ -0,0 +1,108 @@
+use std::fs;
+
+const PRUNE_MODULO: u64 = 16777216;
+// price changes go from -9 to 9, so a window of 4 changes fits in 19^4 slots
+const WINDOW_SLOTS: usize = 19*19*19*19;
+
+#[derive(Debug)]
+struct Puzzle {
+    secrets: Vec<u64>
+}
+
+fn parse_puzzle(text: &str) -> Puzzle {
+    Puzzle {
+        secrets: text.split_whitespace().map(|num| num.parse::<u64>().unwrap()).collect()
+    }
+}
+
+fn mix_and_prune(secret: u64, value: u64) -> u64 {
+    (secret ^ value) % PRUNE_MODULO
+}
+
+fn evolve(secret: u64) -> u64 {
+    let secret = mix_and_prune(secret, secret * 64);
+    let secret = mix_and_prune(secret, secret / 32);
+    mix_and_prune(secret, secret * 2048)
+}
+
+// yields the successive secret numbers (not including the initial one)
+pub struct SecretNumbers {
+    secret: u64
+}
+
+impl SecretNumbers {
+    pub fn new(initial: u64) -> Self {
+        Self {
+            secret: initial
+        }
+    }
+}
+
+impl Iterator for SecretNumbers {
+    type Item = u64;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.secret = evolve(self.secret);
+        Some(self.secret)
+    }
+}
+
+fn solve_pt1(pb: &Puzzle) -> u64 {
+    pb.secrets.iter()
+        .map(|&initial| SecretNumbers::new(initial).nth(1999).unwrap())
+        .sum::<u64>()
+}
+
+fn solve_pt2(pb: &Puzzle) -> u32 {
+    // bananas collected for each window of 4 changes, across all buyers
+    let mut totals: Vec<u32> = vec![0; WINDOW_SLOTS];
+    // last buyer that saw each window (+1 so 0 means never), monkeys sell on the first occurrence only
+    let mut seen_by: Vec<u32> = vec![0; WINDOW_SLOTS];
+    for (buyer, &initial) in pb.secrets.iter().enumerate() {
+        let stamp = buyer as u32 + 1;
+        let mut window = 0;
+        let mut prev_price = (initial % 10) as i32;
+        for (i, secret) in SecretNumbers::new(initial).take(2000).enumerate() {
+            let price = (secret % 10) as i32;
+            let change = (price - prev_price + 9) as usize;
+            window = (window * 19 + change) % WINDOW_SLOTS;
+            prev_price = price;
+            if i >= 3 && seen_by[window] != stamp {
+                seen_by[window] = stamp;
+                totals[window] += price as u32;
+            }
+        }
+    }
+    totals.into_iter().max().unwrap_or(0)
+}
+
+pub fn day22() {
+    let text = fs::read_to_string("inputs/day22.txt").unwrap();
+    let pb = parse_puzzle(&text);
+    let soln = solve_pt1(&pb);
+    println!("Solution to day 22 part 1: {}", soln);
+    let soln2 = solve_pt2(&pb);
+    println!("Solution to day 22 part 2: {}", soln2);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_puzzle, solve_pt1, solve_pt2, SecretNumbers};
+
+    #[test]
+    fn test_secret_sequence() {
+        let secrets: Vec<u64> = SecretNumbers::new(123).take(10).collect();
+        assert_eq!(secrets, vec![15887950, 16495136, 527345, 704524, 1553684, 12683156, 11100544, 12249484, 7753432, 5908254]);
+    }
+
+    #[test]
+    fn test_sample() {
+        let pb = parse_puzzle("1\n10\n100\n2024\n");
+        assert_eq!(solve_pt1(&pb), 37327623);
+    }
+
+    #[test]
+    fn test_sample_pt2() {
+        let pb = parse_puzzle("1\n2\n3\n2024\n");
+        assert_eq!(solve_pt2(&pb), 23);
+    }
+}
@@ -0,0 +1,298 @@
+use std::fs;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GateKind {
+    AND,
+    OR,
+    XOR
+}
+
+#[derive(Debug, Clone)]
+pub struct Gate {
+    pub kind: GateKind,
+    pub inputs: (String, String),
+    pub output: String,
+}
+
+impl Gate {
+    fn apply(&self, lhs: bool, rhs: bool) -> bool {
+        match self.kind {
+            GateKind::AND => lhs && rhs,
+            GateKind::OR => lhs || rhs,
+            GateKind::XOR => lhs ^ rhs
+        }
+    }
+
+    fn has_input(&self, wire: &str) -> bool {
+        self.inputs.0 == wire || self.inputs.1 == wire
+    }
+}
+
+// a network of boolean gates, wires are identified by name
+#[derive(Debug, Clone)]
+pub struct Circuit {
+    pub initial: HashMap<String, bool>,
+    pub gates: Vec<Gate>,
+}
+
+impl Circuit {
+    pub fn parse(text: &str) -> Self {
+        let mut initial = HashMap::new();
+        let mut gates = Vec::new();
+        for line in text.trim().split("\n") {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            if let Some((wire, value)) = line.split_once(": ") {
+                initial.insert(wire.to_string(), value == "1");
+                continue;
+            }
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+            if tokens.len() != 5 || tokens[3] != "->" {
+                panic!("Invalid gate: {}", line);
+            }
+            let kind = match tokens[1] {
+                "AND" => GateKind::AND,
+                "OR" => GateKind::OR,
+                "XOR" => GateKind::XOR,
+                _ => panic!("Unknown gate kind: {}", tokens[1])
+            };
+            gates.push(Gate {
+                kind,
+                inputs: (tokens[0].to_string(), tokens[2].to_string()),
+                output: tokens[4].to_string(),
+            });
+        }
+        Circuit {
+            initial,
+            gates
+        }
+    }
+
+    // gate indexes such that every gate comes after the gates driving its inputs
+    pub fn topological_order(&self) -> Result<Vec<usize>, String> {
+        let mut producers: HashMap<&str, usize> = HashMap::new();
+        for (idx, gate) in self.gates.iter().enumerate() {
+            if producers.insert(&gate.output, idx).is_some() {
+                return Err(format!("wire {} is driven by more than one gate", gate.output));
+            }
+        }
+        let mut pending = vec![0; self.gates.len()];
+        let mut consumers: Vec<Vec<usize>> = vec![vec![]; self.gates.len()];
+        for (idx, gate) in self.gates.iter().enumerate() {
+            for input in [&gate.inputs.0, &gate.inputs.1] {
+                if let Some(&producer) = producers.get(input.as_str()) {
+                    pending[idx] += 1;
+                    consumers[producer].push(idx);
+                }
+            }
+        }
+        let mut queue: VecDeque<usize> = (0..self.gates.len()).filter(|&idx| pending[idx] == 0).collect();
+        let mut order = Vec::with_capacity(self.gates.len());
+        while let Some(idx) = queue.pop_front() {
+            order.push(idx);
+            for &next in &consumers[idx] {
+                pending[next] -= 1;
+                if pending[next] == 0 {
+                    queue.push_back(next);
+                }
+            }
+        }
+        if order.len() != self.gates.len() {
+            return Err("circuit contains a cycle".to_string());
+        }
+        Ok(order)
+    }
+
+    // value of every wire given the input wires
+    pub fn simulate(&self, inputs: &HashMap<String, bool>) -> Result<HashMap<String, bool>, String> {
+        let mut values = inputs.clone();
+        for idx in self.topological_order()? {
+            let gate = &self.gates[idx];
+            let lhs = *values.get(&gate.inputs.0).ok_or(format!("wire {} has no value", gate.inputs.0))?;
+            let rhs = *values.get(&gate.inputs.1).ok_or(format!("wire {} has no value", gate.inputs.1))?;
+            values.insert(gate.output.clone(), gate.apply(lhs, rhs));
+        }
+        Ok(values)
+    }
+
+    // highest bit index among the wires named <prefix><index>
+    fn bits(&self, prefix: char) -> usize {
+        self.initial.keys()
+            .chain(self.gates.iter().map(|gate| &gate.output))
+            .filter_map(|wire| wire.strip_prefix(prefix).and_then(|idx| idx.parse::<usize>().ok()))
+            .map(|idx| idx + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    // checks the gates against the shape of a ripple-carry adder computing z = x + y and
+    // returns the outputs that are wired to the wrong place
+    pub fn find_misplaced_outputs(&self) -> Vec<String> {
+        let last_z = format!("z{:02}", self.bits('z').saturating_sub(1));
+        let is_input = |wire: &str| wire.starts_with('x') || wire.starts_with('y');
+        let is_first_bit = |gate: &Gate| gate.has_input("x00") || gate.has_input("y00");
+        let feeds = |wire: &str, kind: GateKind| {
+            self.gates.iter().any(|gate| gate.kind == kind && gate.has_input(wire))
+        };
+        let mut wrong: HashSet<String> = HashSet::new();
+        for gate in &self.gates {
+            let output = gate.output.as_str();
+            let on_inputs = is_input(&gate.inputs.0) && is_input(&gate.inputs.1);
+            // the z bits are sums (XOR), apart from the last one which is the final carry (OR)
+            if output.starts_with('z') {
+                let expected = if output == last_z { GateKind::OR } else { GateKind::XOR };
+                if gate.kind != expected {
+                    wrong.insert(output.to_string());
+                }
+                continue;
+            }
+            match gate.kind {
+                // x^y is combined with the carry by another XOR, (x^y)^carry must be a z bit.
+                // bit 0 has no carry in, so x00^y00 must be z00 itself
+                GateKind::XOR => {
+                    if !on_inputs || is_first_bit(gate) || !feeds(output, GateKind::XOR) {
+                        wrong.insert(output.to_string());
+                    }
+                },
+                // both halves of the carry are merged by an OR
+                GateKind::AND => {
+                    if !is_first_bit(gate) && !feeds(output, GateKind::OR) {
+                        wrong.insert(output.to_string());
+                    }
+                },
+                GateKind::OR => {}
+            }
+        }
+        let mut ret: Vec<String> = wrong.into_iter().collect();
+        ret.sort();
+        ret
+    }
+}
+
+// reads the wires named <prefix>00, <prefix>01... as a little endian number
+pub fn read_number(values: &HashMap<String, bool>, prefix: char) -> u64 {
+    let mut ret = 0;
+    for (wire, &value) in values {
+        if let Some(idx) = wire.strip_prefix(prefix).and_then(|idx| idx.parse::<u32>().ok()) {
+            if value {
+                ret |= 1 << idx;
+            }
+        }
+    }
+    ret
+}
+
+fn solve_pt1(circuit: &Circuit) -> u64 {
+    let values = circuit.simulate(&circuit.initial).unwrap();
+    read_number(&values, 'z')
+}
+
+fn solve_pt2(circuit: &Circuit) -> String {
+    circuit.find_misplaced_outputs().join(",")
+}
+
+pub fn day24() {
+    let text = fs::read_to_string("inputs/day24.txt").unwrap();
+    let circuit = Circuit::parse(&text);
+    let soln = solve_pt1(&circuit);
+    println!("Solution to day 24 part 1: {}", soln);
+    let soln2 = solve_pt2(&circuit);
+    println!("Solution to day 24 part 2: {}", soln2);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::{read_number, solve_pt1, solve_pt2, Circuit};
+
+    const SAMPLE: &str = r"
+x00: 1
+x01: 1
+x02: 1
+y00: 0
+y01: 1
+y02: 0
+
+x00 AND y00 -> z00
+x01 XOR y01 -> z01
+x02 OR y02 -> z02
+";
+
+    // a correct ripple-carry adder, with the outputs listed in `swaps` exchanged
+    fn adder(bits: usize, swaps: &[(&str, &str)]) -> String {
+        let mut gates = vec![
+            "x00 XOR y00 -> z00".to_string(),
+            "x00 AND y00 -> c00".to_string(),
+        ];
+        for i in 1..bits {
+            let carry_out = if i == bits - 1 { format!("z{:02}", bits) } else { format!("c{:02}", i) };
+            gates.push(format!("x{i:02} XOR y{i:02} -> s{i:02}"));
+            gates.push(format!("x{i:02} AND y{i:02} -> a{i:02}"));
+            gates.push(format!("s{i:02} XOR c{:02} -> z{i:02}", i - 1));
+            gates.push(format!("s{i:02} AND c{:02} -> b{i:02}", i - 1));
+            gates.push(format!("a{i:02} OR b{i:02} -> {}", carry_out));
+        }
+        let swapped: Vec<String> = gates.into_iter().map(|gate| {
+            let (lhs, output) = gate.split_once(" -> ").unwrap();
+            for (a, b) in swaps {
+                if output == *a {
+                    return format!("{} -> {}", lhs, b);
+                } else if output == *b {
+                    return format!("{} -> {}", lhs, a);
+                }
+            }
+            gate.clone()
+        }).collect();
+        let mut text = String::new();
+        for i in 0..bits {
+            text.push_str(&format!("x{i:02}: 0\ny{i:02}: 0\n"));
+        }
+        text.push('\n');
+        text.push_str(&swapped.join("\n"));
+        text
+    }
+
+    fn set_number(inputs: &mut HashMap<String, bool>, prefix: char, bits: usize, value: u64) {
+        for i in 0..bits {
+            inputs.insert(format!("{}{:02}", prefix, i), (value >> i) & 1 == 1);
+        }
+    }
+
+    #[test]
+    fn test_sample() {
+        let circuit = Circuit::parse(SAMPLE);
+        assert_eq!(solve_pt1(&circuit), 4);
+    }
+
+    #[test]
+    fn test_adder_simulation() {
+        let circuit = Circuit::parse(&adder(6, &[]));
+        let mut inputs = circuit.initial.clone();
+        set_number(&mut inputs, 'x', 6, 45);
+        set_number(&mut inputs, 'y', 6, 29);
+        let values = circuit.simulate(&inputs).unwrap();
+        assert_eq!(read_number(&values, 'z'), 74);
+        assert_eq!(solve_pt2(&circuit), "");
+    }
+
+    #[test]
+    fn test_swapped_outputs() {
+        let circuit = Circuit::parse(&adder(8, &[("s03", "a03"), ("z05", "b05")]));
+        assert_eq!(solve_pt2(&circuit), "a03,b05,s03,z05");
+        // the bit 0 sum and carry swapped
+        let circuit = Circuit::parse(&adder(2, &[("z00", "c00")]));
+        assert_eq!(solve_pt2(&circuit), "c00,z00");
+        let circuit = Circuit::parse(&adder(8, &[("z00", "c00"), ("s03", "a03")]));
+        assert_eq!(solve_pt2(&circuit), "a03,c00,s03,z00");
+    }
+
+    #[test]
+    fn test_cycle_is_reported() {
+        // s03 ends up feeding itself
+        let circuit = Circuit::parse(&adder(4, &[("s03", "z03")]));
+        assert!(circuit.simulate(&circuit.initial).is_err());
+    }
+}
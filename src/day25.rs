@@ -0,0 +1,151 @@
+use std::fs;
+
+const ROWS: usize = 7;
+const COLS: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    LOCK,
+    KEY
+}
+
+#[derive(Debug, Clone)]
+struct Schematic {
+    kind: Kind,
+    // number of '#' in each column, not counting the full base row
+    heights: [u8; COLS],
+}
+
+impl Schematic {
+    fn parse(block: &str) -> Self {
+        let rows: Vec<&str> = block.trim().split("\n").map(|line| line.trim()).collect();
+        if rows.len() != ROWS || rows.iter().any(|row| row.len() != COLS) {
+            panic!("Schematics must be {}x{}:\n{}", ROWS, COLS, block);
+        }
+        let kind = if rows[0] == "#####" {
+            Kind::LOCK
+        } else if rows[ROWS - 1] == "#####" {
+            Kind::KEY
+        } else {
+            panic!("Schematic is neither a lock nor a key:\n{}", block);
+        };
+        let mut heights = [0; COLS];
+        for row in &rows[1..ROWS - 1] {
+            for (j, cell) in row.chars().enumerate() {
+                if cell == '#' {
+                    heights[j] += 1;
+                }
+            }
+        }
+        Schematic {
+            kind,
+            heights
+        }
+    }
+
+    // one bit per filled cell, row by row. pins grow down from the top row, keys grow up from the bottom row
+    fn mask(&self) -> u64 {
+        let mut mask = 0;
+        for (j, &height) in self.heights.iter().enumerate() {
+            for filled in 0..=height as usize {
+                let row = match self.kind {
+                    Kind::LOCK => filled,
+                    Kind::KEY => ROWS - 1 - filled
+                };
+                mask |= 1 << (row * COLS + j);
+            }
+        }
+        mask
+    }
+}
+
+#[derive(Debug)]
+struct Puzzle {
+    locks: Vec<Schematic>,
+    keys: Vec<Schematic>,
+}
+
+fn parse_puzzle(text: &str) -> Puzzle {
+    let (locks, keys) = text.trim().split("\n\n")
+        .map(Schematic::parse)
+        .partition(|schematic| schematic.kind == Kind::LOCK);
+    Puzzle {
+        locks,
+        keys
+    }
+}
+
+// a key fits a lock when no cell is filled by both
+fn solve_pt1(pb: &Puzzle) -> usize {
+    let keys: Vec<u64> = pb.keys.iter().map(|key| key.mask()).collect();
+    pb.locks.iter()
+        .map(|lock| {
+            let lock = lock.mask();
+            keys.iter().filter(|&&key| lock & key == 0).count()
+        })
+        .sum::<usize>()
+}
+
+pub fn day25() {
+    let text = fs::read_to_string("inputs/day25.txt").unwrap();
+    let pb = parse_puzzle(&text);
+    let soln = solve_pt1(&pb);
+    println!("Solution to day 25 part 1: {}", soln);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_puzzle, solve_pt1};
+
+    const SAMPLE: &str = r"
+#####
+.####
+.####
+.####
+.#.#.
+.#...
+.....
+
+#####
+##.##
+.#.##
+...##
+...#.
+...#.
+.....
+
+.....
+#....
+#....
+#...#
+#.#.#
+#.###
+#####
+
+.....
+.....
+#.#..
+###..
+###.#
+###.#
+#####
+
+.....
+.....
+.....
+#....
+#.#..
+#.#.#
+#####
+";
+
+    #[test]
+    fn test_sample() {
+        let pb = parse_puzzle(SAMPLE);
+        assert_eq!(pb.locks.len(), 2);
+        assert_eq!(pb.keys.len(), 3);
+        assert_eq!(pb.locks[0].heights, [0, 5, 3, 4, 3]);
+        assert_eq!(pb.keys[0].heights, [5, 0, 2, 1, 3]);
+        assert_eq!(solve_pt1(&pb), 3);
+    }
+}
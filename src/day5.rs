@@ -34,6 +34,14 @@ pub fn day5() {
     let (rules, printing_pages) = utils::parse_page_order_pb(&text);
     let middles = utils::middle_of_correct_orders(&printing_pages, &rules);
     println!("answer day 5 part 1: {}", middles.iter().sum::<i32>());
-    let fixed_middles = utils::middle_of_corrected_orders(&printing_pages, &rules);
-    println!("answer day 5 part 2: {}", fixed_middles.iter().sum::<i32>());
+    match utils::middle_of_corrected_orders(&printing_pages, &rules) {
+        Ok(fixed) => {
+            println!("answer day 5 part 2: {}", fixed.iter().map(|update| update.middle).sum::<i32>());
+            let ambiguous = fixed.iter().filter(|update| !update.unique).count();
+            if ambiguous > 0 {
+                println!("warning: {} corrected updates allow more than one order, their middle page may differ", ambiguous);
+            }
+        },
+        Err(cycle) => println!("rules contain a cycle, can't fix orders: {:?}", cycle.pages)
+    }
 }
\ No newline at end of file
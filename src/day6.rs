@@ -1,5 +1,3 @@
-use std::collections::{HashMap, HashSet};
-
 use utils;
 
 fn get_direction_vec(dir: DIRECTION) -> (i32, i32) {
@@ -19,6 +17,23 @@ enum DIRECTION {
     WEST
 }
 
+impl DIRECTION {
+    const ALL: [DIRECTION; 4] = [DIRECTION::NORTH, DIRECTION::EAST, DIRECTION::SOUTH, DIRECTION::WEST];
+
+    fn index(self) -> usize {
+        match self {
+            DIRECTION::NORTH => 0,
+            DIRECTION::EAST => 1,
+            DIRECTION::SOUTH => 2,
+            DIRECTION::WEST => 3,
+        }
+    }
+
+    fn turn_right(self) -> DIRECTION {
+        DIRECTION::ALL[(self.index() + 1) % 4]
+    }
+}
+
 enum TILE {
     NOTHING,
     GUARD(DIRECTION),
@@ -26,14 +41,52 @@ enum TILE {
     UNKNOWN, // in case we add padding
 }
 
+// fixed size set of small integers, one bit each
+struct BitSet {
+    words: Vec<u64>,
+}
+
+impl BitSet {
+    fn new(len: usize) -> Self {
+        BitSet { words: vec![0; len.div_ceil(64)] }
+    }
+
+    // returns false if the value was already there
+    fn insert(&mut self, value: usize) -> bool {
+        let (word, bit) = (value / 64, 1u64 << (value % 64));
+        let fresh = self.words[word] & bit == 0;
+        self.words[word] |= bit;
+        fresh
+    }
+
+    fn len(&self) -> usize {
+        self.words.iter().map(|word| word.count_ones() as usize).sum()
+    }
+}
+
 struct Game {
    board: Vec<Vec<TILE>>,
-   guard_pos: (i32, i32),
-   guard_orientation: DIRECTION,
    rows: usize,
    cols: usize,
-   init_pos: (i32, i32),
+   init_pos: usize,
    init_orientation: DIRECTION,
+   // for each cell and direction, the last cell before a wall (None if the guard walks off the map)
+   jumps: Vec<[Option<usize>; 4]>,
+}
+
+// every (cell, direction) state of the guard, in order
+struct Walk {
+    states: Vec<(usize, DIRECTION)>,
+    looped: bool,
+}
+
+// an obstacle that traps the guard, with the loop it creates as (row, col, direction)
+// after each turn
+#[derive(Debug, Clone, PartialEq)]
+struct Blocker {
+    row: usize,
+    col: usize,
+    cycle: Vec<(usize, usize, DIRECTION)>,
 }
 
 impl Game {
@@ -55,146 +108,188 @@ impl Game {
                 return tile;
                 }).collect()
             }).collect();
-        let mut pos: (i32, i32) = (-1,-1);
+        let rows = parsed.len();
+        let cols = parsed.get(0).unwrap().len();
+        let mut pos = 0;
         let mut orientation: DIRECTION = DIRECTION::NORTH;
         for (i, row) in parsed.iter().enumerate() {
             for j in 0..row.len() {
                 if let Some(TILE::GUARD(dir)) = row.get(j) {
-                    pos = (j as i32, i as i32);
-                    orientation = dir.clone();
+                    pos = j + cols*i;
+                    orientation = *dir;
                     break;
                 }
             }
         }
-        let rows = parsed.len();
-        let cols = parsed.get(0).unwrap().len();
-        Game {
+        let mut game = Game {
             board: parsed,
-            guard_pos: pos,
-            guard_orientation: orientation,
             rows,
             cols,
             init_pos: pos,
             init_orientation: orientation,
-        }
+            jumps: vec![],
+        };
+        game.jumps = game.build_jumps();
+        game
     }
 
-    pub fn rotate_guard(&mut self) {
-        self.guard_orientation = match self.guard_orientation {
-            DIRECTION::EAST => DIRECTION::SOUTH,
-            DIRECTION::NORTH => DIRECTION::EAST,
-            DIRECTION::SOUTH => DIRECTION::WEST,
-            DIRECTION::WEST => DIRECTION::NORTH 
-        };
+    fn coords(&self, cell: usize) -> (usize, usize) {
+        (cell / self.cols, cell % self.cols)
     }
 
-    pub fn set_empty(&mut self, x: i32, y: i32) {
-        if let Some(row) = self.board.get(y as usize) {
-            if let Some(_cell) = row.get(x as usize) {
-                self.board[y as usize][x as usize] = TILE::NOTHING;
-            }
-        }
+    fn is_wall(&self, cell: usize) -> bool {
+        let (i, j) = self.coords(cell);
+        matches!(self.board[i][j], TILE::WALL)
     }
 
-    fn set_tile(&mut self, row: usize, col: usize, tile: TILE) {
-        self.board[row][col] = tile;
+    // neighbouring cell in the given direction, None when leaving the map
+    fn step(&self, cell: usize, dir: DIRECTION) -> Option<usize> {
+        let (i, j) = self.coords(cell);
+        let (dx, dy) = get_direction_vec(dir);
+        let (ni, nj) = (i as i32 + dy, j as i32 + dx);
+        if ni < 0 || nj < 0 || ni >= self.rows as i32 || nj >= self.cols as i32 {
+            return None;
+        }
+        Some(nj as usize + self.cols*ni as usize)
     }
 
-    pub fn set_obstacle(&mut self, row: usize, col: usize) {
-        self.set_tile(row, col, TILE::WALL);
+    // how many steps ahead `to` is from `from`, if it is on that line at all
+    fn distance_ahead(&self, from: usize, dir: DIRECTION, to: usize) -> Option<usize> {
+        let ((fi, fj), (ti, tj)) = (self.coords(from), self.coords(to));
+        match dir {
+            DIRECTION::NORTH if fj == tj && ti <= fi => Some(fi - ti),
+            DIRECTION::SOUTH if fj == tj && ti >= fi => Some(ti - fi),
+            DIRECTION::WEST if fi == ti && tj <= fj => Some(fj - tj),
+            DIRECTION::EAST if fi == ti && tj >= fj => Some(tj - fj),
+            _ => None,
+        }
     }
 
-    pub fn remove_obstacle(&mut self, row: usize, col: usize) {
-        self.set_tile(row, col, TILE::NOTHING);
+    fn advance(&self, cell: usize, dir: DIRECTION, steps: usize) -> usize {
+        match dir {
+            DIRECTION::NORTH => cell - steps*self.cols,
+            DIRECTION::SOUTH => cell + steps*self.cols,
+            DIRECTION::WEST => cell - steps,
+            DIRECTION::EAST => cell + steps,
+        }
     }
 
-    pub fn reset(&mut self) {
-        let (x, y) = self.guard_pos;
-        self.board[y as usize][x as usize] = TILE::NOTHING;
-        let (j, i) = self.init_pos;
-        self.board[i as usize][j as usize] = TILE::GUARD(self.init_orientation);
-        self.guard_pos = self.init_pos;
-        self.guard_orientation = self.init_orientation;
+    fn build_jumps(&self) -> Vec<[Option<usize>; 4]> {
+        let cells = self.rows*self.cols;
+        let mut jumps = vec![[None; 4]; cells];
+        for dir in DIRECTION::ALL {
+            // the cell ahead must be done first
+            let order: Vec<usize> = match dir {
+                DIRECTION::NORTH | DIRECTION::WEST => (0..cells).collect(),
+                DIRECTION::SOUTH | DIRECTION::EAST => (0..cells).rev().collect(),
+            };
+            for cell in order {
+                jumps[cell][dir.index()] = match self.step(cell, dir) {
+                    None => None,
+                    Some(ahead) if self.is_wall(ahead) => Some(cell),
+                    Some(ahead) => jumps[ahead][dir.index()],
+                };
+            }
+        }
+        jumps
     }
 
+    // the loop the guard ends up in from this state once an extra obstacle is added, if any
+    fn loops_with_obstacle(&self, start: usize, orientation: DIRECTION, obstacle: usize) -> Option<Vec<(usize, DIRECTION)>> {
+        let mut seen = BitSet::new(4*self.rows*self.cols);
+        let mut path = Vec::new();
+        let (mut pos, mut dir) = (start, orientation);
+        loop {
+            if !seen.insert(4*pos + dir.index()) {
+                let cycle_start = path.iter().position(|&state| state == (pos, dir)).unwrap();
+                return Some(path.split_off(cycle_start));
+            }
+            path.push((pos, dir));
+            let stop = self.jumps[pos][dir.index()];
+            let blocked = self.distance_ahead(pos, dir, obstacle)
+                .filter(|&d| stop.is_none_or(|s| d <= self.distance_ahead(pos, dir, s).unwrap()));
+            pos = match (blocked, stop) {
+                (Some(d), _) => self.advance(pos, dir, d - 1),
+                (None, Some(s)) => s,
+                (None, None) => return None,
+            };
+            dir = dir.turn_right();
+        }
+    }
 }
 
-// return true on loop detected
-fn run_simulation(game: &mut Game) -> (HashMap<i32, HashSet<DIRECTION>>, bool) {
-    // add current starting position to the visited map
-    let mut visited = HashMap::new();
-    visited.insert(game.guard_pos.0 + (game.cols as i32)*game.guard_pos.1, HashSet::from([game.guard_orientation]));
+fn run_simulation(game: &Game) -> Walk {
+    let mut seen = BitSet::new(4*game.rows*game.cols);
+    let mut states = Vec::new();
+    let (mut pos, mut dir) = (game.init_pos, game.init_orientation);
     loop {
-        let (x, y) = game.guard_pos;
-        let (dirx, diry) = get_direction_vec(game.guard_orientation);
-        let new_pos = (x+dirx, y+diry);
-        // out of boundary -> stop condition and no looping detected
-        if new_pos.0 < 0 || new_pos.0 >= game.cols as i32 {
-            break;
-        } else if new_pos.1 < 0 || new_pos.1 >= game.rows as i32 {
-            break;
+        if !seen.insert(4*pos + dir.index()) {
+            return Walk { states, looped: true };
         }
-        if let Some(row) = game.board.get(new_pos.1 as usize)  {
-            if let Some(cell) = row.get(new_pos.0 as usize) {
-                match cell {
-                    TILE::NOTHING => {
-                        // move to the new pos
-                        game.set_empty(x, y);
-                        game.guard_pos = new_pos;
-                        // add to visited map
-                        let raw_pos = new_pos.0 + (game.cols as i32)*new_pos.1;
-                        if let Some(node) = visited.get_mut(&raw_pos) {
-                            if node.contains(&game.guard_orientation) {
-                                // loop detected
-                                return (visited, true);
-                            } else {
-                                node.insert(game.guard_orientation);
-                            }
-                        } else {
-                            visited.insert(raw_pos, HashSet::from([game.guard_orientation]));
-                        }
-                        game.board[new_pos.1 as usize][new_pos.0 as usize] = TILE::GUARD(game.guard_orientation);
-                    },
-                    TILE::WALL => {
-                        game.rotate_guard();
-                    },
-                    TILE::UNKNOWN => {},
-                    _ => {}
-                }
-            }
+        states.push((pos, dir));
+        match game.step(pos, dir) {
+            None => return Walk { states, looped: false },
+            Some(ahead) if game.is_wall(ahead) => dir = dir.turn_right(),
+            Some(ahead) => pos = ahead,
         }
     }
-    return (visited, false);
-}
-
-fn count_ways_to_block(game: &mut Game, original_path: &HashMap<i32, HashSet<DIRECTION>>) -> i32 {
-    let mut possible_blocks = 0;
-    for (key, _) in original_path {
-        let j = (key % (game.cols as i32)) as usize;
-        let i = (key / (game.rows as i32)) as usize;
-        game.reset();
-        let tile = game.board.get(i).unwrap().get(j).unwrap();
-        match tile {
-            TILE::WALL => { continue; },
-            TILE::NOTHING => {
-                game.set_obstacle(i, j);
-            }, 
-            TILE::GUARD(_) => { continue; },
-            TILE::UNKNOWN => { continue; }
+}
+
+fn count_visited(game: &Game, walk: &Walk) -> usize {
+    let mut visited = BitSet::new(game.rows*game.cols);
+    for (pos, _) in &walk.states {
+        visited.insert(*pos);
+    }
+    visited.len()
+}
+
+// an obstacle only matters on the original path, and the guard behaves the same
+// until it first reaches it, so each check starts right in front of it
+fn find_blocking_obstacles(game: &Game, original_path: &Walk) -> Vec<Blocker> {
+    let mut blockers = Vec::new();
+    let mut tried = BitSet::new(game.rows*game.cols);
+    tried.insert(game.init_pos);
+    for &(pos, dir) in &original_path.states {
+        let Some(ahead) = game.step(pos, dir) else { continue };
+        if game.is_wall(ahead) || !tried.insert(ahead) {
+            continue;
         }
-        let (_, looped) = run_simulation(game);
-        if looped {
-            possible_blocks += 1;
+        if let Some(cycle) = game.loops_with_obstacle(pos, dir, ahead) {
+            let (row, col) = game.coords(ahead);
+            let cycle = cycle.into_iter()
+                .map(|(cell, heading)| {
+                    let (i, j) = game.coords(cell);
+                    (i, j, heading)
+                })
+                .collect();
+            blockers.push(Blocker { row, col, cycle });
         }
-        game.remove_obstacle(i, j);
-
     }
-    return possible_blocks;
+    blockers
+}
+
+fn count_ways_to_block(game: &Game, original_path: &Walk) -> i32 {
+    find_blocking_obstacles(game, original_path).len() as i32
 }
 
 pub fn day6() {
     let text = utils::read_file_as_text("inputs/day6.txt");
-    /*let text = r"....#.....
+    let game = Game::parse(&text);
+    let walk = run_simulation(&game);
+    if walk.looped {
+        println!("the guard never leaves the map");
+    }
+    println!("answer day 6 part 1: {}", count_visited(&game, &walk)); // 5212
+    let c = count_ways_to_block(&game, &walk);
+    println!("answer to day 6 part 2: {}", c); // 1767
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{count_visited, count_ways_to_block, find_blocking_obstacles, run_simulation, Blocker, Game, DIRECTION};
+
+    const SAMPLE: &str = r"
+....#.....
 .........#
 ..........
 ..#.......
@@ -204,11 +299,94 @@ pub fn day6() {
 ........#.
 #.........
 ......#...
-";*/
-    let mut game = Game::parse(&text);
-    let (visited, _found_loop) = run_simulation(&mut game);
-    println!("answer day 6 part 1: {}", visited.len()); // 5212
-    game.reset();
-    let c = count_ways_to_block(&mut game, &visited);
-    println!("answer to day 6 part 2: {}", c); // 1767
-}
\ No newline at end of file
+";
+
+    #[test]
+    fn test_sample() {
+        let game = Game::parse(SAMPLE);
+        let walk = run_simulation(&game);
+        assert!(!walk.looped);
+        assert_eq!(count_visited(&game, &walk), 41);
+        assert_eq!(count_ways_to_block(&game, &walk), 6);
+    }
+
+    // every free cell tried one by one with the plain step by step walk
+    fn brute_force_blockers(txt: &str) -> Vec<(usize, usize)> {
+        let lines: Vec<&str> = txt.split("\n").filter(|line| !line.is_empty()).collect();
+        let mut ret = Vec::new();
+        for (i, line) in lines.iter().enumerate() {
+            for (j, c) in line.chars().enumerate() {
+                if c != '.' {
+                    continue;
+                }
+                let mut blocked = lines.clone();
+                let edited = format!("{}#{}", &line[..j], &line[j + 1..]);
+                blocked[i] = &edited;
+                if run_simulation(&Game::parse(&blocked.join("\n"))).looped {
+                    ret.push((i, j));
+                }
+            }
+        }
+        ret
+    }
+
+    fn blocker_coords(txt: &str) -> Vec<(usize, usize)> {
+        let game = Game::parse(txt);
+        let mut coords: Vec<(usize, usize)> = find_blocking_obstacles(&game, &run_simulation(&game)).iter()
+            .map(|blocker| (blocker.row, blocker.col))
+            .collect();
+        coords.sort();
+        coords
+    }
+
+    #[test]
+    fn test_sample_blockers() {
+        let coords = blocker_coords(SAMPLE);
+        assert_eq!(coords, vec![(6, 3), (7, 6), (7, 7), (8, 1), (8, 3), (9, 7)]);
+        assert_eq!(coords, brute_force_blockers(SAMPLE));
+    }
+
+    #[test]
+    fn test_rectangular_maps() {
+        let wide = r"
+.#..........
+..........#.
+............
+#^..........
+............
+";
+        let tall = r"
+.#..
+...#
+....
+#...
+....
+..#.
+....
+.^..
+....
+";
+        for txt in [wide, tall] {
+            assert_eq!(blocker_coords(txt), brute_force_blockers(txt));
+        }
+        assert_eq!(blocker_coords(tall), vec![(4, 0), (4, 2)]);
+        let game = Game::parse(wide);
+        let blockers = find_blocking_obstacles(&game, &run_simulation(&game));
+        assert_eq!(blockers, vec![Blocker {
+            row: 4,
+            col: 9,
+            cycle: vec![(3, 9, DIRECTION::WEST), (3, 1, DIRECTION::NORTH), (1, 1, DIRECTION::EAST), (1, 9, DIRECTION::SOUTH)]
+        }]);
+    }
+
+    #[test]
+    fn test_jumps() {
+        let game = Game::parse(SAMPLE);
+        // from the start going north the guard stops right below the wall at (0, 4)
+        assert_eq!(game.jumps[game.init_pos][0], Some(4 + 10));
+        // nothing to the west of the start on row 6 but the wall at (6, 1)
+        assert_eq!(game.jumps[game.init_pos][3], Some(2 + 60));
+        // nothing south of the start
+        assert_eq!(game.jumps[game.init_pos][2], None);
+    }
+}
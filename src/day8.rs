@@ -1,20 +1,22 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
 use utils;
 use itertools::Itertools;
 
-#[derive(Debug, Clone, Hash)]
+// frequency is carried along but does not count when comparing positions
+#[derive(Debug, Clone)]
 struct Point {
     x: i32,
     y: i32,
-    //freq: char
+    freq: char
 }
 
 impl Point {
-    pub fn new(row: usize, col: usize, _freq: char) -> Self {
+    pub fn new(row: usize, col: usize, freq: char) -> Self {
         Point {
             x: col as i32,
             y: row as i32,
-            //freq: freq
+            freq
         }
     }
 
@@ -22,7 +24,7 @@ impl Point {
         Point {
             x: self.x - other.x,
             y: self.y - other.y,
-            //freq: self.freq
+            freq: self.freq
         }
     }
 
@@ -30,7 +32,25 @@ impl Point {
         Point {
             x: self.x + other.x,
             y: self.y + other.y,
-            //freq: self.freq
+            freq: self.freq
+        }
+    }
+
+    pub fn scaled(&self, factor: i32) -> Point {
+        Point {
+            x: self.x * factor,
+            y: self.y * factor,
+            freq: self.freq,
+        }
+    }
+
+    // smallest grid step in the same direction
+    pub fn reduced(&self) -> Point {
+        let divisor = gcd(self.x.abs(), self.y.abs()).max(1);
+        Point {
+            x: self.x / divisor,
+            y: self.y / divisor,
+            freq: self.freq,
         }
     }
 
@@ -48,6 +68,23 @@ impl PartialEq for Point {
 
 impl Eq for Point {}
 
+impl Hash for Point {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (self.x, self.y).hash(state);
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Antinode {
+    pos: Point,
+    freq: char,
+    antennas: (Point, Point),
+}
+
+fn gcd(a: i32, b: i32) -> i32 {
+    if b == 0 { a } else { gcd(b, a % b) }
+}
+
 fn parse_input(txt: &str) -> Vec<Vec<char>> {
     txt.split("\n")
         .filter(| line | { !line.is_empty() })
@@ -71,8 +108,6 @@ fn find_pairs(board: &[Vec<char>]) -> Vec<(Point, Point)> {
     }
     let mut pairs: Vec<(Point, Point)> = Vec::new();
     for (_element, list) in mapping {
-        // note to keep in mind - some points may be on the same axis (gcd > 1)
-        // but it doesn't show in input so we are safe
         for combo in list.into_iter().combinations(2) {
             pairs.push((combo.get(0).unwrap().clone(), combo.get(1).unwrap().clone()));
         }
@@ -81,38 +116,48 @@ fn find_pairs(board: &[Vec<char>]) -> Vec<(Point, Point)> {
     return pairs;
 }
 
-fn compute_antinodes(pairs: &[(Point, Point)]) -> Vec<Point> {
-   pairs.iter().map(| (p1, p2) | {
-       let diff = p2.minus(p1);
-       vec![p1.minus(&diff), p2.plus(&diff)]
-   }).flatten()
-   .collect()
-}
-
-fn compute_antinodes_p2(pairs: &[(Point, Point)], rows: usize, cols: usize) -> Vec<Point> {
+// without resonance, antinodes are the points in line with both antennas and twice as far
+// from one as from the other: one beyond each antenna, plus the two points splitting the
+// gap in thirds when it lands on the grid. With resonance, every grid point on the line counts.
+fn compute_antinodes(pairs: &[(Point, Point)], rows: usize, cols: usize, resonance: bool) -> Vec<Antinode> {
    pairs.iter().map(| (p1, p2) | {
        let diff = p2.minus(p1);
+       if !resonance {
+           let mut antinodes = vec![p1.minus(&diff), p2.plus(&diff)];
+           if diff.x % 3 == 0 && diff.y % 3 == 0 {
+               let third = Point { x: diff.x / 3, y: diff.y / 3, freq: diff.freq };
+               antinodes.push(p1.plus(&third));
+               antinodes.push(p1.plus(&third.scaled(2)));
+           }
+           return tag_antinodes(antinodes, p1, p2);
+       }
+       let step = diff.reduced();
        let mut a_in_line = Vec::new();
-       a_in_line.push(p1.clone());
-       let mut line1 = p1.minus(&diff);
+       let mut line1 = p1.clone();
        while line1.within_bounds(rows, cols) {
            a_in_line.push(line1.clone());
-           line1 = line1.minus(&diff);
+           line1 = line1.minus(&step);
        }
-       a_in_line.push(p2.clone());
-       let mut line2 = p2.plus(&diff);
+       let mut line2 = p1.plus(&step);
        while line2.within_bounds(rows, cols) {
            a_in_line.push(line2.clone());
-           line2 = line2.plus(&diff);
+           line2 = line2.plus(&step);
        }
-       //vec![p1.minus(&diff), p2.plus(&diff)]
-       return a_in_line;
+       return tag_antinodes(a_in_line, p1, p2);
    }).flatten()
    .collect()
 }
 
-fn valid_antinodes(antinodes: &[Point], rows: usize, columns: usize) -> Vec<Point> {
-    antinodes.into_iter().filter(| pt | {
+fn tag_antinodes(points: Vec<Point>, p1: &Point, p2: &Point) -> Vec<Antinode> {
+    points.into_iter()
+        .map(|pos| Antinode { pos, freq: p1.freq, antennas: (p1.clone(), p2.clone()) })
+        .collect()
+}
+
+fn valid_antinodes(antinodes: &[Antinode], rows: usize, columns: usize) -> Vec<Point> {
+    antinodes.into_iter()
+    .map(|antinode| &antinode.pos)
+    .filter(| pt | {
         pt.within_bounds(rows, columns)
     })
     .unique()
@@ -120,49 +165,97 @@ fn valid_antinodes(antinodes: &[Point], rows: usize, columns: usize) -> Vec<Poin
     .collect()
 }
 
+#[derive(Debug, PartialEq)]
+struct FrequencyBreakdown {
+    // distinct antinode positions per frequency
+    counts: BTreeMap<char, usize>,
+    // positions shared by the antinodes of two frequencies
+    overlaps: BTreeMap<(char, char), usize>,
+    total: usize,
+}
+
+fn antinode_frequencies(antinodes: &[Antinode], rows: usize, cols: usize) -> BTreeMap<(i32, i32), BTreeSet<char>> {
+    let mut ret: BTreeMap<(i32, i32), BTreeSet<char>> = BTreeMap::new();
+    for antinode in antinodes.iter().filter(|antinode| antinode.pos.within_bounds(rows, cols)) {
+        ret.entry((antinode.pos.y, antinode.pos.x)).or_default().insert(antinode.freq);
+    }
+    ret
+}
+
+fn frequency_breakdown(antinodes: &[Antinode], rows: usize, cols: usize) -> FrequencyBreakdown {
+    let positions = antinode_frequencies(antinodes, rows, cols);
+    let mut counts = BTreeMap::new();
+    let mut overlaps = BTreeMap::new();
+    for freqs in positions.values() {
+        for freq in freqs {
+            *counts.entry(*freq).or_insert(0) += 1;
+        }
+        for (a, b) in freqs.iter().tuple_combinations() {
+            *overlaps.entry((*a, *b)).or_insert(0) += 1;
+        }
+    }
+    FrequencyBreakdown {
+        counts,
+        overlaps,
+        total: positions.len(),
+    }
+}
+
+const COLORS: [u8; 6] = [31, 32, 33, 34, 35, 36];
+
+// the map with a # on every free antinode cell, optionally colored by frequency
+// (bold white where frequencies overlap)
+fn render_antinodes(board: &[Vec<char>], antinodes: &[Antinode], colored: bool) -> String {
+    let cols = board.first().map_or(0, |row| row.len());
+    let positions = antinode_frequencies(antinodes, board.len(), cols);
+    let palette: BTreeMap<char, u8> = board.iter().flatten()
+        .filter(|c| **c != '.')
+        .collect::<BTreeSet<_>>().into_iter()
+        .enumerate()
+        .map(|(idx, c)| (*c, COLORS[idx % COLORS.len()]))
+        .collect();
+    let mut buffer = String::new();
+    for (i, row) in board.iter().enumerate() {
+        for (j, element) in row.iter().enumerate() {
+            let freqs = positions.get(&(i as i32, j as i32));
+            let (c, freq) = match freqs {
+                Some(freqs) if *element == '.' => ('#', if freqs.len() == 1 { freqs.first().copied() } else { None }),
+                _ => (*element, Some(*element)),
+            };
+            if !colored || c == '.' {
+                buffer.push(c);
+                continue;
+            }
+            match freq.and_then(|f| palette.get(&f)) {
+                Some(code) => buffer.push_str(&format!("\x1b[{}m{}\x1b[0m", code, c)),
+                None => buffer.push_str(&format!("\x1b[1;37m{}\x1b[0m", c)),
+            }
+        }
+        buffer.push('\n');
+    }
+    buffer
+}
+
 fn solve_pt1(board: &[Vec<char>]) -> usize {
     let pairs = find_pairs(board);
-    let antinodes = compute_antinodes(&pairs);
-    //println!("Antinodes: {:?}", antinodes);
     let cols = board.get(0).unwrap().len();
+    let antinodes = compute_antinodes(&pairs, board.len(), cols, false);
     let valids = valid_antinodes(&antinodes, board.len(), cols);
-    // draw for debugging:
-    //for (i, row) in board.iter().enumerate() {
-    //    for (j, element) in row.iter().enumerate() {
-    //        if *element == '.' &&  valids.contains(&Point::new(i, j, '_')) {
-    //            print!("#");
-    //        } else {
-    //            print!("{}", element);
-    //        }
-    //    }
-    //    print!("\n");
-    //}
-
     return valids.len();
 }
 
-fn solve_pt2(board: &[Vec<char>]) -> usize {
+// also hands back the antinodes, for the breakdown
+fn solve_pt2(board: &[Vec<char>]) -> (usize, Vec<Antinode>) {
     let pairs = find_pairs(board);
     let cols = board.get(0).unwrap().len();
     let rows = board.len();
-    let antinodes = compute_antinodes_p2(&pairs, rows, cols);
-    //println!("Antinodes: {:?}", antinodes);
+    let antinodes = compute_antinodes(&pairs, rows, cols, true);
     let valids = valid_antinodes(&antinodes, rows, cols);
-    // draw for debugging:
-    //for (i, row) in board.iter().enumerate() {
-    //    for (j, element) in row.iter().enumerate() {
-    //        if *element == '.' && valids.contains(&Point::new(i, j, *element)) {
-    //            print!("#");
-    //        } else {
-    //            print!("{}", element);
-    //        }
-    //    }
-    //    print!("\n");
-    //}
-
-    return valids.len();
+    return (valids.len(), antinodes);
 }
 
+// draw the part 2 antinodes and list the antennas behind shared ones
+const SHOW_ANTINODES: bool = false;
 
 pub fn day8() {
     let text = utils::read_file_as_text("inputs/day8.txt");
@@ -183,6 +276,115 @@ pub fn day8() {
     let board = parse_input(&text);
     let sol_p1 = solve_pt1(&board);
     println!("answer day 8 part 1: {}", sol_p1); // 220
-    let sol_p2 = solve_pt2(&board);
+    let (sol_p2, antinodes) = solve_pt2(&board);
     println!("answer to day 8 part 2: {}", sol_p2); // 818
+    let cols = board.first().unwrap().len();
+    let breakdown = frequency_breakdown(&antinodes, board.len(), cols);
+    println!("day 8 part 2 antinodes per frequency: {:?}", breakdown.counts);
+    if SHOW_ANTINODES {
+        print!("{}", render_antinodes(&board, &antinodes, true));
+        for ((row, col), freqs) in antinode_frequencies(&antinodes, board.len(), cols) {
+            if freqs.len() < 2 {
+                continue;
+            }
+            let sources: Vec<String> = antinodes.iter()
+                .filter(|antinode| antinode.pos.y == row && antinode.pos.x == col)
+                .map(|antinode| {
+                    let (a, b) = &antinode.antennas;
+                    format!("{} ({},{})-({},{})", antinode.freq, a.y, a.x, b.y, b.x)
+                })
+                .collect();
+            println!("({},{}): {}", row, col, sources.join(", "));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use super::{compute_antinodes, find_pairs, frequency_breakdown, parse_input, render_antinodes, solve_pt1, solve_pt2, Point};
+
+    const SAMPLE: &str = r"
+............
+........0...
+.....0......
+.......0....
+....0.......
+......A.....
+............
+............
+........A...
+.........A..
+............
+............
+";
+
+    #[test]
+    fn test_sample() {
+        let board = parse_input(SAMPLE);
+        assert_eq!(solve_pt1(&board), 14);
+        assert_eq!(solve_pt2(&board).0, 34);
+    }
+
+    #[test]
+    fn test_resonance_gcd() {
+        // (0, 0) and (2, 4) share a line through (1, 2)
+        let board = parse_input("a........\n.........\n....a....\n");
+        assert_eq!(solve_pt1(&board), 0);
+        assert_eq!(solve_pt2(&board).0, 3);
+        // a gap of 3 puts the whole row in line, and both inner points are twice as far from one antenna
+        let board = parse_input("a..a......\n..........\n");
+        assert_eq!(solve_pt1(&board), 3);
+        assert_eq!(solve_pt2(&board).0, 10);
+        // pairs further apart along a steep line reduce to the same step
+        let board = parse_input("b...\n....\n.b..\n....\n..b.\n....\n...b\n");
+        assert_eq!(solve_pt2(&board).0, 4);
+    }
+
+    #[test]
+    fn test_render_sample() {
+        let board = parse_input(SAMPLE);
+        let antinodes = compute_antinodes(&find_pairs(&board), 12, 12, false);
+        let expected = r"
+......#....#
+...#....0...
+....#0....#.
+..#....0....
+....0....#..
+.#....A.....
+...#........
+#......#....
+........A...
+.........A..
+..........#.
+..........#.
+";
+        assert_eq!(render_antinodes(&board, &antinodes, false), expected.trim_start());
+        let colored = render_antinodes(&board, &antinodes, true);
+        assert!(colored.contains("\x1b[32m#\x1b[0m"));
+        assert!(colored.contains("\x1b[31m0\x1b[0m"));
+    }
+
+    #[test]
+    fn test_frequency_breakdown() {
+        // a and b both put an antinode on (0, 1)
+        let board = parse_input("....\n.ab.\n.a.b\n....\n");
+        let antinodes = compute_antinodes(&find_pairs(&board), 4, 4, false);
+        let at_overlap: Vec<_> = antinodes.iter().filter(|antinode| antinode.pos == Point::new(0, 1, '.')).collect();
+        assert_eq!(at_overlap.len(), 2);
+        let b = at_overlap.iter().find(|antinode| antinode.freq == 'b').unwrap();
+        assert_eq!(b.antennas, (Point::new(1, 2, 'b'), Point::new(2, 3, 'b')));
+        assert_eq!(b.antennas.0.freq, 'b');
+        let breakdown = frequency_breakdown(&antinodes, 4, 4);
+        assert_eq!(breakdown.counts, BTreeMap::from([('a', 2), ('b', 1)]));
+        assert_eq!(breakdown.overlaps, BTreeMap::from([(('a', 'b'), 1)]));
+        assert_eq!(breakdown.total, 2);
+        assert!(render_antinodes(&board, &antinodes, true).starts_with(".\x1b[1;37m#\x1b[0m.."));
+
+        let board = parse_input(SAMPLE);
+        let breakdown = frequency_breakdown(&compute_antinodes(&find_pairs(&board), 12, 12, true), 12, 12);
+        assert_eq!(breakdown.total, 34);
+        let shared: usize = breakdown.overlaps.values().sum();
+        assert_eq!(breakdown.counts.values().sum::<usize>() - shared, 34);
+    }
 }
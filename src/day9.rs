@@ -1,4 +1,6 @@
 use std::fs;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
 
 const RADIX: u32 = 10;
 
@@ -25,142 +27,223 @@ fn parse_problem(pb: &str) -> Vec<(u32, u32)> {
     return vec;
 }
 
-fn show_structure(file: &[(u32, u32)]) -> String {
-    let mut buffer: Vec<char> = Vec::new();
-    for (_id, (file_size, free_blocks)) in file.into_iter().enumerate() {
-        for _ in 0..*file_size {
-            //let to_print = char::from_digit(id.try_into().unwrap(), RADIX).unwrap();
-            let to_print = '#';
-            buffer.push(to_print);
-        }
-        for _ in 0..*free_blocks {
-            buffer.push('.');
-        }
-    }
-    return buffer.into_iter().collect();
+// (file id, start, len)
+type FileSpan = (u32, usize, usize);
+
+const MAX_SPAN: usize = 9;
+
+// the disk as spans: files (possibly split in several fragments) and free (start, len) gaps
+#[derive(Debug, Clone, PartialEq)]
+struct DiskMap {
+    files: Vec<FileSpan>,
+    free: Vec<(usize, usize)>,
+    size: usize,
 }
 
-fn print_fs(filesystem: &[Option<u32>]) -> String {
-    let mut buffer: Vec<char> = Vec::new();
-    for i in filesystem {
-        if i.is_none() {
-            buffer.push('.');
-        } else {
-            buffer.push('#');
+impl DiskMap {
+    fn parse(text: &str) -> Self {
+        Self::from_problem(&parse_problem(text.trim()))
+    }
+
+    // empty files are kept where they are so the dense format can be rebuilt
+    fn from_problem(file: &[(u32, u32)]) -> Self {
+        let mut files = Vec::new();
+        let mut free = Vec::new();
+        let mut pos = 0;
+        for (id, (file_size, free_blocks)) in file.iter().enumerate() {
+            files.push((id as u32, pos, *file_size as usize));
+            pos += *file_size as usize;
+            if *free_blocks > 0 {
+                free.push((pos, *free_blocks as usize));
+            }
+            pos += *free_blocks as usize;
         }
+        DiskMap { files, free, size: pos }
     }
-    return buffer.into_iter().collect();
-}
 
-fn deduce_fs(file: &[(u32, u32)]) -> Vec<Option<u32>> {
-    let mut buffer: Vec<Option<u32>> = Vec::new();
-    for (id, (file_size, free_blocks)) in file.into_iter().enumerate() {
-        for _ in 0..*file_size {
-            buffer.push(Some(id.try_into().unwrap()));
+    // gaps are whatever the files leave, fragments of the same file next to each other are joined
+    fn from_files(mut spans: Vec<FileSpan>, size: usize) -> Self {
+        spans.retain(|(_, _, len)| *len > 0);
+        spans.sort_by_key(|(_, start, _)| *start);
+        let mut files: Vec<FileSpan> = Vec::new();
+        let mut free = Vec::new();
+        let mut pos = 0;
+        for (id, start, len) in spans {
+            if start > pos {
+                free.push((pos, start - pos));
+            }
+            match files.last_mut() {
+                Some((last_id, last_start, last_len)) if *last_id == id && *last_start + *last_len == start => *last_len += len,
+                _ => files.push((id, start, len)),
+            }
+            pos = start + len;
         }
-        for _ in 0..*free_blocks {
-            buffer.push(None);
+        if size > pos {
+            free.push((pos, size - pos));
         }
+        DiskMap { files, free, size }
     }
-    return buffer;
-}
 
-fn compact_fs(filesystem: &mut Vec<Option<u32>>) {
-    let len = filesystem.len();
-    let mut end_pos = len - 1;
-    while filesystem.get(end_pos).unwrap().is_none() {
-        end_pos -= 1;
+    // free spans with the ones around empty files joined
+    fn merged_free(&self) -> Vec<(usize, usize)> {
+        let mut ret: Vec<(usize, usize)> = Vec::new();
+        for (start, len) in &self.free {
+            match ret.last_mut() {
+                Some((last_start, last_len)) if *last_start + *last_len == *start => *last_len += len,
+                _ => ret.push((*start, *len)),
+            }
+        }
+        ret
     }
-    for pos in 0..len {
-        let file_block = filesystem.get(pos).unwrap();
-        if file_block.is_none() {
-            filesystem.swap(pos, end_pos);
-            end_pos -= 1;
+
+    // moves single blocks from the end of the disk into the leftmost free block
+    fn compact_blocks(&self) -> DiskMap {
+        let mut files: Vec<FileSpan> = self.files.iter().filter(|(_, _, len)| *len > 0).copied().collect();
+        files.sort_by_key(|(_, start, _)| *start);
+        let mut gaps = self.merged_free().into_iter();
+        let mut gap = gaps.next();
+        let mut moved = Vec::new();
+        while let (Some((id, start, len)), Some((gap_start, gap_len))) = (files.last_mut(), gap) {
+            if gap_start >= *start {
+                break;
+            }
+            let count = gap_len.min(*len);
+            // take from the end of the file
+            moved.push((*id, gap_start, count));
+            *len -= count;
+            if *len == 0 {
+                files.pop();
+            }
+            gap = if count == gap_len { gaps.next() } else { Some((gap_start + count, gap_len - count)) };
         }
-        while filesystem.get(end_pos).unwrap().is_none() {
-            end_pos -= 1;
+        files.extend(moved);
+        DiskMap::from_files(files, self.size)
+    }
+
+    // moves whole files, highest id first, to the leftmost gap that fits them.
+    // the map needs one span per file with ids in disk order, as parsed from the puzzle,
+    // so the space a file leaves behind never has to be reused. an error otherwise, for
+    // instance after compact_blocks split some files.
+    // gaps are bucketed by size (9 and up together, as any file fits) in min-heaps on
+    // their start, so finding the leftmost fit is a peek at 9 heaps and a leftover gap
+    // is pushed back in its new bucket
+    fn compact_files(&self) -> Result<DiskMap, String> {
+        let mut files = self.files.clone();
+        files.sort_by_key(|(id, start, _)| (*start, *id));
+        if let Some(pair) = files.windows(2).find(|pair| pair[0].0 >= pair[1].0) {
+            return Err(format!("file {} found after file {}, files must be whole and in id order",
+                pair[1].0, pair[0].0));
         }
-        if end_pos <= pos {
-            break;
+        let mut gaps: [BinaryHeap<Reverse<(usize, usize)>>; MAX_SPAN + 1] = std::array::from_fn(|_| BinaryHeap::new());
+        for (start, len) in self.merged_free() {
+            gaps[len.min(MAX_SPAN)].push(Reverse((start, len)));
         }
+        for (_, start, len) in files.iter_mut().rev() {
+            if *len == 0 {
+                continue;
+            }
+            let best = (*len..gaps.len())
+                .filter_map(|bucket| gaps[bucket].peek().map(|Reverse(gap)| (*gap, bucket)))
+                .min();
+            if let Some(((gap, size), bucket)) = best.filter(|((gap, _), _)| gap < start) {
+                gaps[bucket].pop();
+                *start = gap;
+                // the space the file leaves behind is to the right of every file still to move
+                if size > *len {
+                    let rest = size - *len;
+                    gaps[rest.min(MAX_SPAN)].push(Reverse((gap + *len, rest)));
+                }
+            }
+        }
+        Ok(DiskMap::from_files(files, self.size))
     }
-}
 
-fn swap_blocks(filesystem: &mut Vec<Option<u32>>, start: usize, end: usize, file_size: usize) {
-    for i in 0..file_size {
-        filesystem.swap(start+i, end-file_size+i+1);
+    fn checksum(&self) -> u64 {
+        self.files.iter()
+            .map(|(id, start, len)| (*start..*start + *len).map(|pos| pos as u64 * *id as u64).sum::<u64>())
+            .sum()
     }
-}
 
-// O(n^2) non-optimal!
-fn compact_fs_p2(filesystem: &mut Vec<Option<u32>>, files: &[(u32, u32)]) {
-    let mut end_pos = filesystem.len() - 1;
-    let mut moved_files = vec![false; files.len()];
-    while end_pos > 0 {
-        let mut current_node = filesystem.get(end_pos).unwrap();
-        while current_node.is_none() {
-          end_pos -= 1;
-          current_node = filesystem.get(end_pos).unwrap();
-        }
-        if let Some(id) = current_node {
-            let (file_size, _) = files.get(*id as usize).unwrap(); 
-            if moved_files[*id as usize] {
-                // already processed
-                let shift_by = *file_size as usize;
-                end_pos -= shift_by;
-                continue;
+    fn blocks(&self) -> Vec<Option<u32>> {
+        let mut ret = vec![None; self.size];
+        for (id, start, len) in &self.files {
+            for block in &mut ret[*start..*start + *len] {
+                *block = Some(*id);
             }
-            //println!("Processing file ID {} at {} with size {}", *id, end_pos, file_size);
-            // look from the beginning:
-            let mut i = 0;
-            while i < end_pos {
-                if let Some(occupied) = filesystem.get(i).unwrap() {
-                    let (skip_by, _) = files.get(*occupied as usize).unwrap();
-                    i += *skip_by as usize;
-                } else {
-                  // found empty slot
-                    let mut empty_size = 1;
-                    while filesystem.get(i+empty_size).unwrap().is_none() {
-                        empty_size += 1;
-                    }
-                    //println!("Found empty slot at i: {} with size {}", i, empty_size);
-                    if empty_size >= (*file_size).try_into().unwrap() {
-                        //println!("{:?}", filesystem);
-                        //println!("Swapping ID {} at {} with {} (file_size {})", *id, i, end_pos, *file_size);
-                        moved_files[*id as usize] = true;
-                        // swap blocks [i+file_size) and (end_pos-file_size; end_pos]
-                        swap_blocks(filesystem, i, end_pos, *file_size as usize);
-                        //println!("{:?}", filesystem);
-                        //end_pos -= *file_size as usize;
-                        break;
-                    } else {
-                        // look for next empty spot
-                        //println!("Empty size not big enough for size {}", *file_size);
-                        i += empty_size;
-                    }
-                }
-                //i += 1;
+        }
+        ret
+    }
+
+    // each block as its file id on `width` characters (keeping the lowest digits when it
+    // does not fit), free blocks as dots
+    fn render(&self, width: usize) -> String {
+        self.blocks().iter()
+            .map(|block| match block {
+                Some(id) => {
+                    let digits = id.to_string();
+                    format!("{:>width$}", &digits[digits.len().saturating_sub(width)..], width = width)
+                },
+                None => ".".repeat(width),
+            })
+            .collect()
+    }
+
+    // back to the puzzle format, only possible when files are whole and in id order.
+    // a trailing empty gap is left out, as in the puzzle input
+    #[cfg(test)]
+    fn to_dense(&self) -> Result<String, String> {
+        let mut files = self.files.clone();
+        files.sort_by_key(|(id, start, _)| (*start, *id));
+        let mut digits = Vec::new();
+        for (idx, (id, start, len)) in files.iter().enumerate() {
+            if *id as usize != idx {
+                return Err(format!("file {} found where file {} was expected", id, idx));
             }
-            //end_pos -= 1;
-            if (end_pos as i32 - *file_size as i32) < 0 {
-                break;
-            } else {
-                end_pos -= *file_size as usize;
+            let end = files.get(idx + 1).map_or(self.size, |(_, next, _)| *next);
+            let gap = end - start - len;
+            if *len > MAX_SPAN || gap > MAX_SPAN {
+                return Err(format!("file {} or the gap after it is longer than {}", id, MAX_SPAN));
+            }
+            digits.push(*len);
+            if gap > 0 || idx + 1 < files.len() {
+                digits.push(gap);
             }
         }
+        Ok(digits.iter().map(|d| char::from_digit(*d as u32, RADIX).unwrap()).collect())
     }
-}
 
-fn compute_checksum(filesystem: &[Option<u32>]) -> u64 {
-    let mut checksum: u64 = 0;
-    for (pos, block) in filesystem.into_iter().enumerate() {
-        if let Some(id) = block {
-            checksum += pos as u64 * (*id as u64);
+    // spans must tile the disk exactly: sorted, no overlap and no hole
+    #[cfg(test)]
+    fn check_invariants(&self) -> Result<(), String> {
+        let mut spans: Vec<(usize, usize, Option<u32>)> = self.files.iter()
+            .filter(|(_, _, len)| *len > 0)
+            .map(|(id, start, len)| (*start, *len, Some(*id)))
+            .chain(self.free.iter().map(|(start, len)| (*start, *len, None)))
+            .collect();
+        spans.sort();
+        let mut pos = 0;
+        for (start, len, id) in spans {
+            if len == 0 {
+                return Err(format!("empty free span at {}", start));
+            }
+            if start < pos {
+                return Err(format!("span {:?} at {} overlaps the previous one ending at {}", id, start, pos));
+            }
+            if start > pos {
+                return Err(format!("blocks {}..{} are neither free nor used", pos, start));
+            }
+            pos = start + len;
         }
+        if pos != self.size {
+            return Err(format!("spans cover {} blocks out of {}", pos, self.size));
+        }
+        Ok(())
     }
-    return checksum;
 }
 
+// print the disk before and after each compaction
+const SHOW_DISK: bool = false;
+
 pub fn day9() {
     //let text = r"12345";
     /*let text = r"
@@ -168,20 +251,168 @@ pub fn day9() {
     //let text = read_file("inputs/evil.txt"); // 97898222299196
     let text = read_file("inputs/day9.txt");
     //let text = read_file("inputs/more_evil.txt"); // 5799706413896802
-    let files = parse_problem(&text.trim());
-    let mut filesystem = deduce_fs(&files);
-    //println!("{:?}", filesystem);
-    compact_fs(&mut filesystem);
-    //println!("compact fs: {}", print_fs(&filesystem));
-    //println!("{:?}", filesystem);
-    let mut checksum = compute_checksum(&filesystem);
-
-    println!("Solution to day 9 part 1: {}", checksum); // 6390180901651
-    filesystem = deduce_fs(&files);
-    //println!("{:?}", filesystem);
-    compact_fs_p2(&mut filesystem, &files);
-    //println!("{:?}", filesystem);
-    //println!("compact fs: {}", print_fs(&filesystem));
-    checksum = compute_checksum(&filesystem);
-    println!("Solution to day 9 part 2: {}", checksum); // 6412390114238 
+    let disk = DiskMap::parse(&text);
+    let blocks = disk.compact_blocks();
+    let files = disk.compact_files().expect("Puzzle maps have whole files");
+    if SHOW_DISK {
+        // wide enough for the highest id
+        let width = disk.files.len().saturating_sub(1).to_string().len() + 1;
+        println!("disk:       {}", disk.render(width));
+        println!("blocks:     {}", blocks.render(width));
+        println!("files:      {}", files.render(width));
+    }
+
+    println!("Solution to day 9 part 1: {}", blocks.checksum()); // 6390180901651
+    println!("Solution to day 9 part 2: {}", files.checksum()); // 6412390114238
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use crate::testing::Lcg;
+    use super::DiskMap;
+
+    const SAMPLE: &str = "2333133121414131402";
+
+    fn checksum(filesystem: &[Option<u32>]) -> u64 {
+        filesystem.iter().enumerate()
+            .map(|(pos, block)| block.map_or(0, |id| pos as u64 * id as u64))
+            .sum()
+    }
+
+    // last used block into the first free one, one at a time
+    fn compact_blocks_naive(filesystem: &mut [Option<u32>]) {
+        loop {
+            let first_free = filesystem.iter().position(|block| block.is_none());
+            let last_used = filesystem.iter().rposition(|block| block.is_some());
+            match (first_free, last_used) {
+                (Some(free), Some(used)) if free < used => filesystem.swap(free, used),
+                _ => break,
+            }
+        }
+    }
+
+    // block by block, scanning from the left for every file
+    fn compact_files_naive(filesystem: &mut [Option<u32>]) {
+        let max_id = filesystem.iter().flatten().max().copied().unwrap_or(0);
+        for id in (0..=max_id).rev() {
+            let Some(start) = filesystem.iter().position(|block| *block == Some(id)) else { continue };
+            let len = filesystem.iter().filter(|block| **block == Some(id)).count();
+            let gap = (0..start).find(|&pos| filesystem[pos..pos + len].iter().all(|block| block.is_none()));
+            if let Some(gap) = gap {
+                for i in 0..len {
+                    filesystem.swap(gap + i, start + i);
+                }
+            }
+        }
+    }
+
+    fn file_sizes(disk: &DiskMap) -> BTreeMap<u32, usize> {
+        let mut ret = BTreeMap::new();
+        for (id, _, len) in &disk.files {
+            *ret.entry(*id).or_insert(0) += len;
+        }
+        ret.retain(|_, len| *len > 0);
+        ret
+    }
+
+    // small deterministic pseudo-random dense maps, zero sized files and gaps included
+    fn random_maps(seed: u64, count: usize, max_len: u64) -> Vec<String> {
+        let mut rng = Lcg::new(seed);
+        (0..count)
+            .map(|_| {
+                let len = 1 + rng.below(max_len);
+                (0..len).map(|_| char::from_digit(rng.below(10) as u32, 10).unwrap()).collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sample() {
+        let disk = DiskMap::parse(SAMPLE);
+        assert_eq!(disk.render(1), "00...111...2...333.44.5555.6666.777.888899");
+        let blocks = disk.compact_blocks();
+        assert_eq!(blocks.render(1), "0099811188827773336446555566..............");
+        assert_eq!(blocks.checksum(), 1928);
+        let files = disk.compact_files().unwrap();
+        assert_eq!(files.render(1), "00992111777.44.333....5555.6666.....8888..");
+        assert_eq!(files.checksum(), 2858);
+    }
+
+    #[test]
+    fn test_render_width() {
+        let disk = DiskMap::parse("10203040506070809010111");
+        // ids 10 and 11 do not fit on one character
+        assert!(disk.render(1).ends_with("88888888890.1"));
+        assert!(disk.render(2).ends_with(" 8 8 910..11"));
+        assert_eq!(disk.render(3).len(), 3 * disk.size);
+        assert_eq!(DiskMap::parse("12").render(3), "  0......");
+    }
+
+    #[test]
+    fn test_dense_round_trip() {
+        for text in random_maps(5, 200, 30).iter().filter(|text| text.len() % 2 == 1) {
+            assert_eq!(DiskMap::parse(text).to_dense().as_deref(), Ok(text.as_str()));
+        }
+        assert_eq!(DiskMap::parse(SAMPLE).to_dense(), Ok(SAMPLE.to_string()));
+        // file 9 now sits before file 2
+        assert!(DiskMap::parse(SAMPLE).compact_files().unwrap().to_dense().is_err());
+        assert_eq!(DiskMap::parse("1311").compact_files().unwrap().to_dense(), Ok("1014".to_string()));
+        // the 12 free blocks left at the end do not fit in a digit
+        assert!(DiskMap::parse("1913").compact_files().unwrap().to_dense().is_err());
+    }
+
+    #[test]
+    fn test_invariants() {
+        let disk = DiskMap::parse(SAMPLE);
+        assert_eq!(disk.check_invariants(), Ok(()));
+        let mut overlapping = disk.clone();
+        overlapping.files[1].1 -= 1;
+        assert!(overlapping.check_invariants().is_err());
+        let mut hole = disk.clone();
+        hole.free.pop();
+        assert!(hole.check_invariants().is_err());
+        let mut shrunk = disk.clone();
+        shrunk.size -= 1;
+        assert!(shrunk.check_invariants().is_err());
+    }
+
+    #[test]
+    fn test_compaction_properties() {
+        for text in random_maps(99, 300, 40) {
+            let disk = DiskMap::parse(&text);
+            assert_eq!(disk.check_invariants(), Ok(()), "{}", text);
+            let mut dense_blocks = disk.blocks();
+            compact_blocks_naive(&mut dense_blocks);
+            let mut dense_files = disk.blocks();
+            compact_files_naive(&mut dense_files);
+            for (compacted, dense) in [(disk.compact_blocks(), dense_blocks), (disk.compact_files().unwrap(), dense_files)] {
+                assert_eq!(compacted.check_invariants(), Ok(()), "{}", text);
+                assert_eq!(compacted.size, disk.size);
+                assert_eq!(file_sizes(&compacted), file_sizes(&disk));
+                assert_eq!(compacted.blocks(), dense, "{}", text);
+                assert_eq!(compacted.checksum(), checksum(&dense));
+            }
+        }
+    }
+
+    #[test]
+    fn test_compact_files_needs_whole_files() {
+        // block compaction splits files 8 and 6 in several fragments
+        let err = DiskMap::parse(SAMPLE).compact_blocks().compact_files().unwrap_err();
+        assert!(err.contains("in id order"));
+        // whole files out of id order do not do either
+        let swapped = DiskMap::from_files(vec![(1, 0, 2), (0, 3, 1)], 5);
+        assert!(swapped.compact_files().is_err());
+        assert!(DiskMap::parse(SAMPLE).compact_files().unwrap().compact_files().is_err());
+    }
+
+    #[test]
+    fn test_compact_files_large_gaps() {
+        // every file fits in the first gap, which shrinks one block at a time
+        let disk = DiskMap::parse(&format!("9{}", "91".repeat(2000)));
+        let mut dense = disk.blocks();
+        compact_files_naive(&mut dense);
+        assert_eq!(disk.compact_files().unwrap().checksum(), checksum(&dense));
+    }
 }
@@ -21,7 +21,12 @@ mod day18;
 mod day19;
 mod day20;
 mod day21;
+mod day22;
 mod day23;
+mod day24;
+mod day25;
+#[cfg(test)]
+mod testing;
 
 fn main() {
     let args: Vec<String> = env::args().collect();
@@ -53,10 +58,10 @@ fn main() {
         19 => day19::day19(),
         20 => day20::day20(),
         21 => day21::day21(),
-        22 => println!("Not implemented"),
+        22 => day22::day22(),
         23 => day23::day23(),
-        24 => println!("Not implemented"),
-        25 => println!("Not implemented"),
+        24 => day24::day24(),
+        25 => day25::day25(),
         _ => println!("Invalid day"),
     }
 
@@ -0,0 +1,18 @@
+// helpers shared by the day tests
+
+// small deterministic pseudo-random numbers, to generate test cases
+pub struct Lcg {
+    state: u64,
+}
+
+impl Lcg {
+    pub fn new(seed: u64) -> Self {
+        Lcg { state: seed }
+    }
+
+    // next value in 0..modulo
+    pub fn below(&mut self, modulo: u64) -> u64 {
+        self.state = self.state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+        (self.state >> 33) % modulo
+    }
+}
@@ -1,5 +1,6 @@
 use std::fs;
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::Read;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 
 pub fn read_file_as_text(path: &str) -> String {
     let text = fs::read_to_string(path).expect("Unable to read file");
@@ -17,102 +18,530 @@ pub fn parse_line_by_line(txt: &str) -> Vec<Vec<i32>> {
     }).collect()
 }
 
-pub fn is_safe(report: &[i32]) -> i32 {
-    if report.len() == 1 {
-        return 1; // safe
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    INCREASING,
+    DECREASING,
+    // set by the first pair that isn't flat
+    EITHER
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SafetyRules {
+    pub min_step: i32,
+    pub max_step: i32,
+    // equal neighbours are fine and don't set the direction
+    pub allow_equal: bool,
+    pub direction: Direction,
+}
+
+impl Default for SafetyRules {
+    // the puzzle rules
+    fn default() -> Self {
+        SafetyRules {
+            min_step: 1,
+            max_step: 3,
+            allow_equal: false,
+            direction: Direction::EITHER,
+        }
     }
-    let mut increasing = false;
-    for i in 0..report.len()-1 {
+}
+
+impl SafetyRules {
+    fn valid_step(&self, from: i32, to: i32, increasing: bool) -> bool {
+        let diff = if increasing { to - from } else { from - to };
+        if diff == 0 {
+            return self.allow_equal;
+        }
+        diff >= self.min_step.max(1) && diff <= self.max_step
+    }
+}
+
+pub fn is_safe(report: &[i32], rules: &SafetyRules) -> bool {
+    first_failure(report, rules).is_none()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FailureKind {
+    DIRECTION,
+    STEP,
+    EQUAL
+}
+
+// first pair of neighbours (index, index+1) breaking the rules
+#[derive(Debug, Clone, PartialEq)]
+pub struct Failure {
+    pub index: usize,
+    pub kind: FailureKind,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DampenerResult {
+    pub safe: bool,
+    // indexes to drop to make the report safe, empty if it already is or can't be fixed
+    pub removed: Vec<usize>,
+    // why the original report isn't safe
+    pub failure: Option<Failure>,
+}
+
+fn first_failure(report: &[i32], rules: &SafetyRules) -> Option<Failure> {
+    let mut increasing = match rules.direction {
+        Direction::INCREASING => Some(true),
+        Direction::DECREASING => Some(false),
+        Direction::EITHER => None
+    };
+    for i in 0..report.len().saturating_sub(1) {
         let diff = report[i+1] - report[i];
-        if i == 0 {
-            increasing = diff > 0;
+        if diff == 0 {
+            if rules.allow_equal {
+                continue;
+            }
+            return Some(Failure { index: i, kind: FailureKind::EQUAL });
         }
-        if (increasing && diff < 0) || (diff.abs() > 3 || diff.abs() < 1) || (!increasing && diff > 0) {
-            return 0;
+        let going_up = *increasing.get_or_insert(diff > 0);
+        let kind = if (diff > 0) != going_up {
+            FailureKind::DIRECTION
+        } else if !rules.valid_step(report[i], report[i+1], going_up) {
+            FailureKind::STEP
+        } else {
+            continue;
+        };
+        return Some(Failure { index: i, kind });
+    }
+    None
+}
+
+// fewest removals for the report to go in the given direction, the skipped
+// elements between two kept ones are removed so we only look back tolerance+1 steps
+fn min_removals(report: &[i32], tolerance: usize, increasing: bool, rules: &SafetyRules) -> Option<Vec<usize>> {
+    let n = report.len();
+    // best[i]: removals needed before i when keeping i, with the previous kept index
+    let mut best: Vec<(usize, Option<usize>)> = Vec::with_capacity(n);
+    for i in 0..n {
+        let mut current = (i, None);
+        for j in i.saturating_sub(tolerance+1)..i {
+            let removals = best[j].0 + (i-j-1);
+            if removals < current.0 && rules.valid_step(report[j], report[i], increasing) {
+                current = (removals, Some(j));
+            }
         }
+        best.push(current);
     }
-    return 1;
+    let (last, total) = (0..n).map(|i| (i, best[i].0 + (n-1-i))).min_by_key(|&(_, total)| total)?;
+    if total > tolerance {
+        return None;
+    }
+    let mut kept = vec![false; n];
+    let mut current = Some(last);
+    while let Some(i) = current {
+        kept[i] = true;
+        current = best[i].1;
+    }
+    Some((0..n).filter(|&i| !kept[i]).collect())
 }
 
-fn with_dampener<F>(vals: &[i32], func: F) -> i32
-where F: Fn(&[i32]) -> i32 {
-    if func(vals) == 1 {
-        return 1;
+// safe if dropping at most `tolerance` levels makes it safe, in O(n*tolerance)
+pub fn dampen(report: &[i32], tolerance: usize, rules: &SafetyRules) -> DampenerResult {
+    let failure = first_failure(report, rules);
+    if failure.is_none() {
+        return DampenerResult { safe: true, removed: Vec::new(), failure };
     }
-    // try with removing ith element
-    for i in 0..vals.len() {
-        let mut values: Vec<i32> = Vec::new();
-        for j in 0..vals.len() {
-            if j != i {
-                values.push(vals[j]);
+    let directions = match rules.direction {
+        Direction::INCREASING => vec![true],
+        Direction::DECREASING => vec![false],
+        Direction::EITHER => vec![true, false]
+    };
+    let removed = directions.into_iter()
+        .filter_map(|increasing| min_removals(report, tolerance, increasing, rules))
+        .min_by_key(|removed| removed.len());
+    match removed {
+        Some(removed) => DampenerResult { safe: true, removed, failure },
+        None => DampenerResult { safe: false, removed: Vec::new(), failure }
+    }
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct SafetySummary {
+    pub safe: usize,
+    // safe only after removing some levels
+    pub dampened: usize,
+    // reports still unsafe, by why the original report failed
+    pub direction: usize,
+    pub step: usize,
+    pub equal: usize,
+}
+
+// part 1 is a tolerance of 0, part 2 a tolerance of 1
+pub fn check_reports(reports: &[Vec<i32>], rules: &SafetyRules, tolerance: usize) -> (Vec<DampenerResult>, SafetySummary) {
+    let results: Vec<DampenerResult> = reports.iter()
+        .map(|report| dampen(report, tolerance, rules))
+        .collect();
+    let mut summary = SafetySummary::default();
+    for result in &results {
+        if result.safe {
+            summary.safe += 1;
+            if !result.removed.is_empty() {
+                summary.dampened += 1;
             }
+            continue;
         }
-        if func(&values) == 1 {
-            return 1;
+        match result.failure.as_ref().map(|failure| failure.kind) {
+            Some(FailureKind::DIRECTION) => summary.direction += 1,
+            Some(FailureKind::STEP) => summary.step += 1,
+            Some(FailureKind::EQUAL) => summary.equal += 1,
+            None => {}
         }
     }
-    return 0;
+    (results, summary)
 }
 
-pub fn check_safety(reports: &[Vec<i32>]) -> i32 {
-    reports.into_iter()
-        .map(| arg | { is_safe(arg) })
-        .sum::<i32>()
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instruction {
+    MUL(i32, i32),
+    DO,
+    DONT,
+    // any other registered instruction
+    CALL(String, Vec<i32>)
 }
 
-pub fn check_safety_p2(reports: &[Vec<i32>]) -> i32 {
-    reports.into_iter()
-        .map(| arg | { with_dampener(arg, is_safe) })
-        .sum::<i32>()
+impl Instruction {
+    fn from_parts(name: &str, args: Vec<i32>) -> Self {
+        match (name, args.as_slice()) {
+            ("mul", &[a, b]) => Instruction::MUL(a, b),
+            ("do", &[]) => Instruction::DO,
+            ("don't", &[]) => Instruction::DONT,
+            _ => Instruction::CALL(name.to_string(), args)
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        match self {
+            Instruction::MUL(_, _) => "mul",
+            Instruction::DO => "do",
+            Instruction::DONT => "don't",
+            Instruction::CALL(name, _) => name
+        }
+    }
+
+    pub fn args(&self) -> Vec<i32> {
+        match self {
+            Instruction::MUL(a, b) => vec![*a, *b],
+            Instruction::DO | Instruction::DONT => vec![],
+            Instruction::CALL(_, args) => args.clone()
+        }
+    }
 }
 
-use regex::Regex;
-pub fn parse_mul_pairs(text: &str) -> Vec<(i32, i32)> {
-    let re = Regex::new(r"mul\(([0-9]+),([0-9]+)\)").unwrap();
-
-    let mut results = vec![];
-    for (_, [d1, d2]) in re.captures_iter(text).map(|c| c.extract()) {
-        results.push((d1.parse::<i32>().unwrap(), d2.parse::<i32>().unwrap()));
-    }
-    return results;
-}
-
-pub fn parse_do_mul_pairs(text: &str) -> Vec<(i32, i32)> {
-    let re = Regex::new(r"(?:mul\(([0-9]+),([0-9]+)\))|(do\(\))|(don't\(\))").unwrap();
-    //println!("Doing text: {}", text);
-    let mut results = vec![];
-    let mut skip_next = false;
-    re.captures_iter(text).for_each(|c| {
-        let mut d1: i32 = 0;
-        let mut d2: i32 = 0;
-        // todo in a more idiomatic way ^^;
-        for i in 1..c.len() {
-            let curr = c.get(i);
-            if i == 1 && curr.is_some() {
-                d1 = match curr {
-                    Some(a) => a.as_str().parse::<i32>().unwrap(),
-                    None => 0
+impl std::fmt::Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let args: Vec<String> = self.args().iter().map(|arg| arg.to_string()).collect();
+        write!(f, "{}({})", self.name(), args.join(","))
+    }
+}
+
+// an instruction and the byte offset where it starts in the input
+#[derive(Debug, Clone, PartialEq)]
+pub struct Token {
+    pub instruction: Instruction,
+    pub offset: usize,
+}
+
+// `name(a,b,...)` with `arity` arguments of 1 to 3 digits
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstructionSpec {
+    pub name: String,
+    pub arity: usize,
+}
+
+impl InstructionSpec {
+    pub fn new(name: &str, arity: usize) -> Self {
+        InstructionSpec {
+            name: name.to_string(),
+            arity,
+        }
+    }
+
+    // feeds one more byte to a match of this instruction that started earlier
+    fn advance(&self, candidate: &mut Candidate, byte: u8) -> Progress {
+        let name = self.name.as_bytes();
+        candidate.phase = match candidate.phase {
+            Phase::Name(matched) if matched < name.len() => {
+                if byte != name[matched] {
+                    return Progress::Dead;
+                }
+                Phase::Name(matched + 1)
+            },
+            Phase::Name(_) => {
+                if byte != b'(' {
+                    return Progress::Dead;
                 }
-            } else if i == 2 && curr.is_some() {
-                d2 = match curr {
-                    Some(a) => a.as_str().parse::<i32>().unwrap(),
-                    None => 0
+                if self.arity == 0 { Phase::Close } else { Phase::Arg { value: 0, digits: 0 } }
+            },
+            Phase::Arg { value, digits } if byte.is_ascii_digit() && digits < MAX_DIGITS => {
+                Phase::Arg { value: value * 10 + (byte - b'0') as i32, digits: digits + 1 }
+            },
+            Phase::Arg { value, digits } if digits > 0 => {
+                let last = candidate.args.len() + 1 == self.arity;
+                if byte != if last { b')' } else { b',' } {
+                    return Progress::Dead;
+                }
+                candidate.args.push(value);
+                if last {
+                    return Progress::Done;
+                }
+                Phase::Arg { value: 0, digits: 0 }
+            },
+            Phase::Arg { .. } => return Progress::Dead,
+            Phase::Close => return if byte == b')' { Progress::Done } else { Progress::Dead }
+        };
+        Progress::Partial
+    }
+}
+
+pub fn default_instruction_set() -> Vec<InstructionSpec> {
+    vec![InstructionSpec::new("mul", 2), InstructionSpec::new("do", 0), InstructionSpec::new("don't", 0)]
+}
+
+const MAX_DIGITS: usize = 3;
+
+// position inside `name(a,b,...)` for any registered instruction
+#[derive(Debug, Clone, Copy)]
+enum Phase {
+    // number of bytes of the name matched so far
+    Name(usize),
+    Arg { value: i32, digits: usize },
+    // waiting for the ')' of an instruction without arguments
+    Close,
+}
+
+enum Progress {
+    Dead,
+    Partial,
+    Done,
+}
+
+// one spec matched from `start`, with the arguments read so far
+struct Candidate {
+    spec: usize,
+    start: usize,
+    phase: Phase,
+    args: Vec<i32>,
+}
+
+// pulls the instructions out of corrupted memory one byte at a time,
+// so tokens split across two reads are handled like any other
+pub struct InstructionReader<R: Read> {
+    reader: R,
+    specs: Vec<InstructionSpec>,
+    // the specs whose text can begin with each byte value
+    starts: Vec<Vec<usize>>,
+    buffer: Vec<u8>,
+    pos: usize,
+    len: usize,
+    // offset in the input of buffer[0]
+    consumed: usize,
+    // matches still in progress, oldest first
+    candidates: Vec<Candidate>,
+}
+
+impl<R: Read> InstructionReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_instructions(reader, default_instruction_set(), 4096)
+    }
+
+    pub fn with_buffer_size(reader: R, size: usize) -> Self {
+        Self::with_instructions(reader, default_instruction_set(), size)
+    }
+
+    pub fn with_instructions(reader: R, specs: Vec<InstructionSpec>, size: usize) -> Self {
+        let mut starts = vec![Vec::new(); 256];
+        for (idx, spec) in specs.iter().enumerate() {
+            let first = spec.name.bytes().next().unwrap_or(b'(');
+            starts[first as usize].push(idx);
+        }
+        InstructionReader {
+            reader,
+            specs,
+            starts,
+            buffer: vec![0; size.max(1)],
+            pos: 0,
+            len: 0,
+            consumed: 0,
+            candidates: Vec::new(),
+        }
+    }
+
+    // the first match to complete wins, and drops every match it overlaps
+    fn step(&mut self, byte: u8, offset: usize) -> Option<Token> {
+        let specs = &self.specs;
+        let seeds = self.starts[byte as usize].iter().map(|&spec| Candidate { spec, start: offset, phase: Phase::Name(0), args: Vec::new() });
+        self.candidates.extend(seeds);
+        let mut done = None;
+        self.candidates.retain_mut(|candidate| {
+            if done.is_some() {
+                return false;
+            }
+            match specs[candidate.spec].advance(candidate, byte) {
+                Progress::Dead => false,
+                Progress::Partial => true,
+                Progress::Done => {
+                    done = Some((candidate.spec, candidate.start, std::mem::take(&mut candidate.args)));
+                    false
                 }
             }
-            if i == 3 && curr.is_some() {
-                skip_next = false;
-                break;
+        });
+        let (spec, start, args) = done?;
+        self.candidates.clear();
+        Some(Token { instruction: Instruction::from_parts(&self.specs[spec].name, args), offset: start })
+    }
+}
+
+impl<R: Read> Iterator for InstructionReader<R> {
+    type Item = std::io::Result<Token>;
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.pos == self.len {
+                self.consumed += self.len;
+                self.pos = 0;
+                self.len = match self.reader.read(&mut self.buffer) {
+                    Ok(0) => return None,
+                    Ok(len) => len,
+                    Err(err) if err.kind() == std::io::ErrorKind::Interrupted => 0,
+                    Err(err) => return Some(Err(err))
+                };
+                continue;
             }
-            if i == 4 && curr.is_some() {
-                skip_next = true;
-                break;
+            let byte = self.buffer[self.pos];
+            let offset = self.consumed + self.pos;
+            self.pos += 1;
+            if let Some(token) = self.step(byte, offset) {
+                return Some(Ok(token));
             }
         }
-        if !skip_next {
-            results.push((d1, d2));
+    }
+}
+
+pub fn tokenize(text: &str) -> Vec<Token> {
+    // reading from a slice can't fail
+    InstructionReader::new(text.as_bytes()).map(|token| token.unwrap()).collect()
+}
+
+pub fn mul_pairs(tokens: &[Token]) -> Vec<(i32, i32)> {
+    tokens.iter().filter_map(|token| match token.instruction {
+        Instruction::MUL(a, b) => Some((a, b)),
+        _ => None
+    }).collect()
+}
+
+pub fn parse_mul_pairs(text: &str) -> Vec<(i32, i32)> {
+    mul_pairs(&tokenize(text))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Machine {
+    pub enabled: bool,
+    pub accumulator: i64,
+}
+
+// returns whether the instruction changed the accumulator
+pub type Handler = Box<dyn Fn(&mut Machine, &[i32]) -> bool>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEntry {
+    pub token: Token,
+    pub counted: bool,
+    pub accumulator: i64,
+}
+
+pub struct Interpreter {
+    pub machine: Machine,
+    pub trace: Vec<TraceEntry>,
+    specs: Vec<InstructionSpec>,
+    handlers: HashMap<String, Handler>,
+}
+
+impl Default for Interpreter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Interpreter {
+    // knows no instructions at all
+    pub fn new() -> Self {
+        Interpreter {
+            machine: Machine { enabled: true, accumulator: 0 },
+            trace: Vec::new(),
+            specs: Vec::new(),
+            handlers: HashMap::new(),
+        }
+    }
+
+    pub fn register(&mut self, name: &str, arity: usize, handler: Handler) {
+        self.specs.retain(|spec| spec.name != name);
+        self.specs.push(InstructionSpec::new(name, arity));
+        self.handlers.insert(name.to_string(), handler);
+    }
+
+    // part 1: every mul counts
+    pub fn part1() -> Self {
+        let mut interpreter = Self::new();
+        interpreter.register("mul", 2, Box::new(|machine, args| {
+            machine.accumulator += args[0] as i64 * args[1] as i64;
+            true
+        }));
+        interpreter
+    }
+
+    // part 2: mul only counts when enabled, do() and don't() toggle it
+    pub fn part2() -> Self {
+        let mut interpreter = Self::new();
+        interpreter.register("mul", 2, Box::new(|machine, args| {
+            if machine.enabled {
+                machine.accumulator += args[0] as i64 * args[1] as i64;
+            }
+            machine.enabled
+        }));
+        interpreter.register("do", 0, Box::new(|machine, _| {
+            machine.enabled = true;
+            false
+        }));
+        interpreter.register("don't", 0, Box::new(|machine, _| {
+            machine.enabled = false;
+            false
+        }));
+        interpreter
+    }
+
+    // the instructions the tokenizer has to look for
+    pub fn instruction_set(&self) -> Vec<InstructionSpec> {
+        self.specs.clone()
+    }
+
+    pub fn execute(&mut self, token: Token) {
+        let counted = match self.handlers.get(token.instruction.name()) {
+            Some(handler) => handler(&mut self.machine, &token.instruction.args()),
+            None => false
+        };
+        self.trace.push(TraceEntry { token, counted, accumulator: self.machine.accumulator });
+    }
+
+    pub fn run<R: Read>(&mut self, reader: R) -> std::io::Result<i64> {
+        for token in InstructionReader::with_instructions(reader, self.instruction_set(), 4096) {
+            self.execute(token?);
         }
-    });
-    return results;
+        Ok(self.machine.accumulator)
+    }
+
+    // one line per instruction, saying whether it counted
+    pub fn explain(&self) -> String {
+        let mut ret = String::new();
+        for entry in &self.trace {
+            let status = if entry.counted { "counted" } else { "ignored" };
+            ret.push_str(&format!("{:>8} {:<14} {} -> {}\n", entry.token.offset, entry.token.instruction.to_string(), status, entry.accumulator));
+        }
+        ret
+    }
 }
 
 pub fn compute_multiplication_sum(list: &[(i32, i32)]) -> i32
@@ -130,74 +559,232 @@ pub fn parse_letter_grid(txt: &str) -> Vec<Vec<char>> {
 }
 
 
-// Define a function to check if a cell is within the grid
-fn is_valid(grid: &[Vec<char>], i: i32, j: i32) -> bool {
-    let rows = grid.len();
-    let cols = grid[0].len();
+// (dx, dy) pairs, dx moving along a row and dy down the rows
+pub const DIRECTIONS: [(i32, i32); 8] = [(-1,-1), (-1, 0), (-1,1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)];
+
+// Aho-Corasick automaton, finds every word of a dictionary in a single pass over some text
+struct WordAutomaton {
+    transitions: Vec<HashMap<char, usize>>,
+    fail: Vec<usize>,
+    // index of the words ending in each state (including through fail links)
+    outputs: Vec<Vec<usize>>,
+}
+
+impl WordAutomaton {
+    fn new(words: &[&str]) -> Self {
+        let mut transitions: Vec<HashMap<char, usize>> = vec![HashMap::new()];
+        let mut outputs: Vec<Vec<usize>> = vec![vec![]];
+        for (idx, word) in words.iter().enumerate() {
+            let mut state = 0;
+            for c in word.chars() {
+                state = match transitions[state].get(&c) {
+                    Some(&next) => next,
+                    None => {
+                        transitions.push(HashMap::new());
+                        outputs.push(vec![]);
+                        let next = transitions.len() - 1;
+                        transitions[state].insert(c, next);
+                        next
+                    }
+                };
+            }
+            if state != 0 {
+                outputs[state].push(idx);
+            }
+        }
+        // breadth first so fail links always point to states already done
+        let mut fail = vec![0; transitions.len()];
+        let mut queue: VecDeque<usize> = transitions[0].values().copied().collect();
+        while let Some(state) = queue.pop_front() {
+            let children: Vec<(char, usize)> = transitions[state].iter().map(|(&c, &next)| (c, next)).collect();
+            for (c, next) in children {
+                let mut fallback = fail[state];
+                while fallback != 0 && !transitions[fallback].contains_key(&c) {
+                    fallback = fail[fallback];
+                }
+                fail[next] = transitions[fallback].get(&c).copied().filter(|&target| target != next).unwrap_or(0);
+                let inherited = outputs[fail[next]].clone();
+                outputs[next].extend(inherited);
+                queue.push_back(next);
+            }
+        }
+        WordAutomaton {
+            transitions,
+            fail,
+            outputs,
+        }
+    }
 
-    (i >= 0 && i < rows as i32) && (j >= 0 && j < cols as i32)
+    // (index of the last char, word index) for every occurrence
+    fn find_all(&self, text: &[char]) -> Vec<(usize, usize)> {
+        let mut ret = Vec::new();
+        let mut state = 0;
+        for (pos, c) in text.iter().enumerate() {
+            while state != 0 && !self.transitions[state].contains_key(c) {
+                state = self.fail[state];
+            }
+            state = self.transitions[state].get(c).copied().unwrap_or(0);
+            for &word in &self.outputs[state] {
+                ret.push((pos, word));
+            }
+        }
+        ret
+    }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub struct WordHit {
+    pub word: String,
+    pub row: usize,
+    pub col: usize,
+    pub direction: (i32, i32),
+}
 
-// Define a function to check if a pattern exists at position (i, j) in the grid
-fn has_pattern(grid: &[Vec<char>], i: usize, j: usize, target: &str, th: usize, (dx,dy): (i32, i32)) ->
-bool {
-    //let cols = grid[0].len()
-    if let Some(c) = target.chars().nth(th) {
-        if grid[i][j] == c {
-            if th == target.len() - 1 {
-                return true;
-            } else {
-                if is_valid(grid, i as i32 + dy, j as i32 + dx) {
-                    let y: usize = (i as i32 + dy) as usize;
-                    let x: usize = (j as i32 + dx) as usize;
-                    return has_pattern(grid, y, x, target, th+1, (dx, dy));
-                } else {
-                    return false;
+// every occurrence of every word, reading in any of the 8 directions
+pub fn find_words(grid: &[Vec<char>], words: &[&str]) -> Vec<WordHit> {
+    let automaton = WordAutomaton::new(words);
+    let lengths: Vec<usize> = words.iter().map(|word| word.chars().count()).collect();
+    let mut hits = Vec::new();
+    for (dx, dy) in DIRECTIONS {
+        for (i, row) in grid.iter().enumerate() {
+            for j in 0..row.len() {
+                // only start lines on cells with nothing before them in this direction
+                let (prev_i, prev_j) = (i as i32 - dy, j as i32 - dx);
+                if prev_i >= 0 && prev_j >= 0 && grid.get(prev_i as usize).and_then(|line| line.get(prev_j as usize)).is_some() {
+                    continue;
+                }
+                let mut cells = Vec::new();
+                let (mut y, mut x) = (i as i32, j as i32);
+                while let Some(&c) = grid.get(y as usize).and_then(|line| line.get(x as usize)).filter(|_| y >= 0 && x >= 0) {
+                    cells.push((y as usize, x as usize, c));
+                    y += dy;
+                    x += dx;
+                }
+                let line: Vec<char> = cells.iter().map(|&(_, _, c)| c).collect();
+                for (end, word) in automaton.find_all(&line) {
+                    let (row, col, _) = cells[end + 1 - lengths[word]];
+                    hits.push(WordHit { word: words[word].to_string(), row, col, direction: (dx, dy) });
                 }
             }
         }
-        return false;
-    } else {
-        return false;
     }
+    hits
 }
 
 pub fn findall_in_grid(grid: &[Vec<char>], target: &str) -> i32 {
-    let mut soln: i32 = 0;
-    for (i, row) in grid.iter().enumerate() {
-        for (j, &_cell) in row.iter().enumerate() {
-            let dxdy = vec![(-1,-1), (-1, 0), (-1,1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)];
-            for direction in dxdy {
-                if has_pattern(&grid, i, j, target, 0, direction) {
-                    soln += 1;
+    find_words(grid, &[target]).len() as i32
+}
+
+// quarter turns clockwise, applied after the optional left-right mirror
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Orientation {
+    pub rotation: u32,
+    pub reflected: bool,
+}
+
+// a small grid of letters to look for, None cells match anything
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    cells: Vec<Vec<Option<char>>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TemplateMatch {
+    // top left corner of the (oriented) template in the grid
+    pub row: usize,
+    pub col: usize,
+    pub orientation: Orientation,
+}
+
+impl Template {
+    pub fn parse(text: &str, wildcard: char) -> Self {
+        let cells: Vec<Vec<Option<char>>> = text.split_whitespace()
+            .map(|line| line.chars().map(|c| if c == wildcard { None } else { Some(c) }).collect())
+            .collect();
+        if cells.is_empty() || cells.iter().any(|row| row.len() != cells[0].len()) {
+            panic!("Template must be a non empty rectangle");
+        }
+        Template { cells }
+    }
+
+    // `word` along both diagonals of a square, crossing in the middle
+    pub fn cross(word: &str) -> Self {
+        let letters: Vec<char> = word.chars().collect();
+        let n = letters.len();
+        let mut cells = vec![vec![None; n]; n];
+        for (i, &letter) in letters.iter().enumerate() {
+            cells[i][i] = Some(letter);
+            cells[i][n-1-i] = Some(letter);
+        }
+        Template { cells }
+    }
+
+    pub fn rows(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cells[0].len()
+    }
+
+    pub fn rotate(&self) -> Self {
+        let (rows, cols) = (self.rows(), self.cols());
+        let cells = (0..cols).map(|i| (0..rows).map(|j| self.cells[rows-1-j][i]).collect()).collect();
+        Template { cells }
+    }
+
+    pub fn reflect(&self) -> Self {
+        let cells = self.cells.iter().map(|row| row.iter().rev().copied().collect()).collect();
+        Template { cells }
+    }
+
+    // every distinct way to lay the template down, symmetric ones show up once
+    pub fn orientations(&self) -> Vec<(Orientation, Template)> {
+        let mut ret: Vec<(Orientation, Template)> = Vec::new();
+        for reflected in [false, true] {
+            let mut current = if reflected { self.reflect() } else { self.clone() };
+            for rotation in 0..4 {
+                if !ret.iter().any(|(_, other)| *other == current) {
+                    ret.push((Orientation { rotation, reflected }, current.clone()));
                 }
+                current = current.rotate();
             }
         }
+        ret
+    }
+
+    fn matches_at(&self, grid: &[Vec<char>], row: usize, col: usize) -> bool {
+        self.cells.iter().enumerate().all(|(i, cells)| {
+            cells.iter().enumerate().all(|(j, cell)| match cell {
+                None => true,
+                Some(c) => grid.get(row+i).and_then(|line| line.get(col+j)) == Some(c)
+            })
+        })
     }
-    return soln;
 }
 
-pub fn findall_x_in_grid(grid: &[Vec<char>], target: &str) -> i32 {
-    let mut soln: i32 = 0;
-    let target_rev = target.chars().rev().collect::<String>();
-    for (i, row) in grid.iter().enumerate() {
-        for j in 0..row.len()-2 {
-            // col,row
-            let pairs = vec![/* ((1,-1),(-1,1)),*/ ((1,1), (-1,1))];
-            for (dxdy1, dxdy2) in pairs {
-                if (has_pattern(&grid, i, j, target, 0, dxdy1) ||
-                    has_pattern(&grid, i, j, &target_rev, 0, dxdy1)) && (
-                    has_pattern(grid, i, j+2, target, 0, dxdy2) ||
-                    has_pattern(grid, i, j+2, &target_rev, 0, dxdy2)) 
-                {
-                    //println!("Soln at {}, {}", i, j);
-                    soln += 1;
+pub fn match_template(grid: &[Vec<char>], template: &Template, all_orientations: bool) -> Vec<TemplateMatch> {
+    let orientations = if all_orientations {
+        template.orientations()
+    } else {
+        vec![(Orientation { rotation: 0, reflected: false }, template.clone())]
+    };
+    let mut ret = Vec::new();
+    for (orientation, oriented) in orientations {
+        for row in 0..grid.len() {
+            for col in 0..grid[row].len() {
+                if oriented.matches_at(grid, row, col) {
+                    ret.push(TemplateMatch { row, col, orientation });
                 }
             }
         }
     }
-    return soln;
+    ret
+}
+
+// `target` crossing itself on the diagonals, in any direction
+pub fn findall_x_in_grid(grid: &[Vec<char>], target: &str) -> i32 {
+    match_template(grid, &Template::cross(target), true).len() as i32
 }
 
 pub fn parse_page_order_pb(txt: &str) -> (HashMap<i32, HashSet<i32>>, Vec<Vec<i32>>) {
@@ -279,47 +866,287 @@ pub fn middle_of_correct_orders(printing_pages: &[Vec<i32>], rules: &HashMap<i32
         }).collect()
 }
 
-fn fix_order(order: &[i32], rules: &HashMap<i32, HashSet<i32>>, violating_idx: (usize, usize)) -> Vec<i32> {
-    // swap violating indexes until it's good?
-    // assumption: there is always a solution
-    // println!("Bad idx {:?} for order: {:?}", violating_idx, order);
-    let mut trial = Vec::from(order);
-    let (idx1, idx2) = violating_idx;
-    trial.swap(idx1, idx2);
-    loop {
-        if let Some((viol1, viol2)) = get_violation_idx(&trial, rules) {
-            trial.swap(viol1, viol2);
-        } else {
-            break;
+#[derive(Debug, PartialEq)]
+pub struct SortedUpdate {
+    pub order: Vec<i32>,
+    // false when the rules allow more than one order for these pages
+    pub unique: bool,
+}
+
+// pages forming a cycle in the rules, each one must come before the next (and the last before the first)
+#[derive(Debug, PartialEq)]
+pub struct OrderCycle {
+    pub pages: Vec<i32>,
+}
+
+// topological sort of the rules restricted to the pages of the update,
+// ties are broken by keeping the original relative order
+pub fn sort_update(update: &[i32], rules: &HashMap<i32, HashSet<i32>>) -> Result<SortedUpdate, OrderCycle> {
+    let n = update.len();
+    // successors[i] holds the indexes that have to be printed after update[i]
+    let mut successors: Vec<Vec<usize>> = vec![vec![]; n];
+    let mut pending = vec![0; n];
+    for (i, page) in update.iter().enumerate() {
+        if let Some(set) = rules.get(page) {
+            for (j, other) in update.iter().enumerate() {
+                if i != j && set.contains(other) {
+                    successors[i].push(j);
+                    pending[j] += 1;
+                }
+            }
         }
     }
-    return trial;
+    let mut ready: Vec<usize> = (0..n).filter(|&i| pending[i] == 0).collect();
+    let mut order = Vec::with_capacity(n);
+    let mut unique = true;
+    while !ready.is_empty() {
+        if ready.len() > 1 {
+            unique = false;
+        }
+        // ready is kept sorted, smallest index first
+        let idx = ready.remove(0);
+        order.push(update[idx]);
+        for &next in &successors[idx] {
+            pending[next] -= 1;
+            if pending[next] == 0 {
+                let pos = ready.binary_search(&next).unwrap_err();
+                ready.insert(pos, next);
+            }
+        }
+    }
+    if order.len() == n {
+        return Ok(SortedUpdate { order, unique });
+    }
+    // every page left has a predecessor that is left too, walk back until we loop
+    let mut predecessor = vec![None; n];
+    for (i, list) in successors.iter().enumerate() {
+        if pending[i] > 0 {
+            for &j in list {
+                if pending[j] > 0 {
+                    predecessor[j] = Some(i);
+                }
+            }
+        }
+    }
+    let mut current = (0..n).find(|&i| pending[i] > 0).unwrap();
+    let mut seen = vec![false; n];
+    while !seen[current] {
+        seen[current] = true;
+        current = predecessor[current].unwrap();
+    }
+    let mut cycle = vec![current];
+    let mut walker = predecessor[current].unwrap();
+    while walker != current {
+        cycle.push(walker);
+        walker = predecessor[walker].unwrap();
+    }
+    cycle.reverse();
+    // start from the page that shows up first in the update
+    let first = (0..cycle.len()).min_by_key(|&i| cycle[i]).unwrap();
+    cycle.rotate_left(first);
+    Err(OrderCycle { pages: cycle.into_iter().map(|i| update[i]).collect() })
 }
 
-pub fn middle_of_corrected_orders(printing_pages: &[Vec<i32>], rules: &HashMap<i32, HashSet<i32>>) -> Vec<i32> {
+#[derive(Debug, PartialEq)]
+pub struct CorrectedMiddle {
+    pub middle: i32,
+    // false when the rules allow other orders, which may have another middle page
+    pub unique: bool,
+}
+
+pub fn middle_of_corrected_orders(printing_pages: &[Vec<i32>], rules: &HashMap<i32, HashSet<i32>>) -> Result<Vec<CorrectedMiddle>, OrderCycle> {
     printing_pages.iter()
-    .map(|list| { (list, get_violation_idx(list, rules)) })
-    .filter(|(_list, violation)| {violation.is_some()})
-    .map(|(incorrect_order, violation)| {
-        fix_order(incorrect_order, rules, violation.unwrap())
+    .filter(|list| { get_violation_idx(list, rules).is_some() })
+    .map(|incorrect_order| {
+        sort_update(incorrect_order, rules)
     })
-    .map( |list| {
-        let idx = list.len()/2;
-        //println!("Order {:?}", list);
-        if let Some(val) = list.get(idx) {
-            //println!("Middle: {}", val);
-            return val.clone();
-        } else {
-            return 0;
-        }
+    .map( |sorted| {
+        let sorted = sorted?;
+        let idx = sorted.order.len()/2;
+        Ok(CorrectedMiddle { middle: sorted.order.get(idx).copied().unwrap_or(0), unique: sorted.unique })
     }).collect()
 }
 
-#[derive(Clone)]
+#[derive(Debug, PartialEq)]
+pub struct UpdateReport {
+    pub update: Vec<i32>,
+    pub correct: bool,
+    // the rules allow exactly one order for these pages
+    pub total_order: bool,
+    pub cycle: Option<Vec<i32>>,
+    // index pairs to swap, in sequence, to get to the sorted order. when total_order is false this
+    // is only the fewest swaps to the one order picked, another valid order may need fewer
+    pub swaps: Vec<(usize, usize)>,
+    // rules between these pages that a chain through the other pages already implies
+    pub redundant_rules: Vec<(i32, i32)>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RuleAnalysis {
+    // rules that some update applies, but every such update (without a cycle) already implies
+    // through a chain of other rules. the whole rule graph is usually cyclic, so this is
+    // worked out on the pages of each update only
+    pub redundant_rules: Vec<(i32, i32)>,
+    // pages printed in some update but never mentioned by a rule
+    pub unconstrained_pages: Vec<i32>,
+    pub updates: Vec<UpdateReport>,
+}
+
+// whether `to` can be reached from `from` through `pages` only, without taking the direct rule from -> to
+fn reachable_without_rule(rules: &HashMap<i32, HashSet<i32>>, pages: &HashSet<i32>, from: i32, to: i32) -> bool {
+    let mut visited = HashSet::from([from]);
+    let mut stack: Vec<i32> = rules[&from].iter().copied().filter(|next| *next != to && pages.contains(next)).collect();
+    while let Some(page) = stack.pop() {
+        if page == to {
+            return true;
+        }
+        if !visited.insert(page) {
+            continue;
+        }
+        if let Some(set) = rules.get(&page) {
+            stack.extend(set.iter().copied().filter(|next| pages.contains(next) && !visited.contains(next)));
+        }
+    }
+    false
+}
+
+// the rules between the pages of one update, each with whether the others already imply it
+fn update_rules(rules: &HashMap<i32, HashSet<i32>>, update: &[i32]) -> Vec<((i32, i32), bool)> {
+    let pages: HashSet<i32> = update.iter().copied().collect();
+    let mut ret: Vec<((i32, i32), bool)> = pages.iter()
+        .filter_map(|before| rules.get(before).map(|set| (*before, set)))
+        .flat_map(|(before, set)| set.iter().filter(|after| pages.contains(after)).map(move |&after| (before, after)))
+        .map(|(before, after)| ((before, after), reachable_without_rule(rules, &pages, before, after)))
+        .collect();
+    ret.sort();
+    ret
+}
+
+// swaps turning `update` into `target`, both holding the same pages. repeated pages are matched
+// in order of appearance, so this is the fewest swaps only when pages are distinct
+fn swaps_to_order(update: &[i32], target: &[i32]) -> Vec<(usize, usize)> {
+    let mut slots: HashMap<i32, VecDeque<usize>> = HashMap::new();
+    for (idx, &page) in target.iter().enumerate() {
+        slots.entry(page).or_default().push_back(idx);
+    }
+    // where the page at each index has to go
+    let mut wanted: Vec<usize> = update.iter()
+        .map(|page| slots.get_mut(page).and_then(|free| free.pop_front()).expect("update and target must hold the same pages"))
+        .collect();
+    let mut swaps = Vec::new();
+    for i in 0..wanted.len() {
+        // each swap puts one page where it belongs
+        while wanted[i] != i {
+            let j = wanted[i];
+            wanted.swap(i, j);
+            swaps.push((i, j));
+        }
+    }
+    swaps
+}
+
+pub fn analyze_page_rules(rules: &HashMap<i32, HashSet<i32>>, printing_pages: &[Vec<i32>]) -> RuleAnalysis {
+    // whether each rule applied by some update is needed by at least one of them
+    let mut needed: BTreeMap<(i32, i32), bool> = BTreeMap::new();
+    let mut constrained: HashSet<i32> = HashSet::new();
+    for (&before, set) in rules {
+        constrained.insert(before);
+        constrained.extend(set.iter().copied());
+    }
+    let mut unconstrained_pages: Vec<i32> = printing_pages.iter()
+        .flatten()
+        .copied()
+        .filter(|page| !constrained.contains(page))
+        .collect::<HashSet<i32>>()
+        .into_iter()
+        .collect();
+    unconstrained_pages.sort();
+    let updates = printing_pages.iter().map(|update| {
+        let correct = get_violation_idx(update, rules).is_none();
+        match sort_update(update, rules) {
+            Ok(sorted) => {
+                let mut redundant_rules = Vec::new();
+                for (rule, implied) in update_rules(rules, update) {
+                    *needed.entry(rule).or_insert(false) |= !implied;
+                    if implied {
+                        redundant_rules.push(rule);
+                    }
+                }
+                UpdateReport {
+                    update: update.clone(),
+                    correct,
+                    total_order: sorted.unique,
+                    cycle: None,
+                    swaps: swaps_to_order(update, &sorted.order),
+                    redundant_rules,
+                }
+            },
+            // no chain of rules means much with a cycle among the pages
+            Err(cycle) => UpdateReport {
+                update: update.clone(),
+                correct,
+                total_order: false,
+                cycle: Some(cycle.pages),
+                swaps: Vec::new(),
+                redundant_rules: Vec::new(),
+            }
+        }
+    }).collect();
+    let redundant_rules = needed.into_iter().filter(|(_, needed)| !needed).map(|(rule, _)| rule).collect();
+    RuleAnalysis {
+        redundant_rules,
+        unconstrained_pages,
+        updates,
+    }
+}
+
+impl std::fmt::Display for RuleAnalysis {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "redundant rules: {}", self.redundant_rules.len())?;
+        for (before, after) in &self.redundant_rules {
+            writeln!(f, "  {}|{}", before, after)?;
+        }
+        writeln!(f, "unconstrained pages: {:?}", self.unconstrained_pages)?;
+        for report in &self.updates {
+            let status = if let Some(cycle) = &report.cycle {
+                format!("cycle {:?}", cycle)
+            } else if report.correct {
+                "correct".to_string()
+            } else {
+                format!("{} swaps {:?}", report.swaps.len(), report.swaps)
+            };
+            let order = if report.total_order { "" } else { " (several valid orders)" };
+            writeln!(f, "{:?}: {}{}", report.update, status, order)?;
+        }
+        Ok(())
+    }
+}
+
+// values stay non-negative: SUBTRACT can't go below 0 and DIVIDE has to be exact
+#[derive(Clone, Debug, PartialEq)]
 pub enum OP {
     ADD,
     MULTIPLY,
-    CONCAT
+    CONCAT,
+    SUBTRACT,
+    DIVIDE,
+    XOR,
+    POWER
+}
+
+impl std::fmt::Display for OP {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let symbol = match self {
+            OP::ADD => "+",
+            OP::MULTIPLY => "*",
+            OP::CONCAT => "||",
+            OP::SUBTRACT => "-",
+            OP::DIVIDE => "/",
+            OP::XOR => "^",
+            OP::POWER => "**"
+        };
+        write!(f, "{}", symbol)
+    }
 }
 
 struct OpsCombinatorial<'a> {
@@ -352,7 +1179,7 @@ impl Iterator for OpsCombinatorial<'_> {
         if n != 0 {
             let len = self.current.len();
             let max = self.ops.len().pow(len as u32);
-            if n > max as u64 {
+            if n >= max as u64 {
                 return None;
             }
             // set all the OPS in the vector
@@ -370,15 +1197,45 @@ impl Iterator for OpsCombinatorial<'_> {
     }
 }
 
-fn concat_u64(lhs: u64, rhs: u64) -> u64 {
-    let mut base = 10;
+// smallest power of 10 above rhs, None if it doesn't fit in a u64
+fn concat_base(rhs: u64) -> Option<u64> {
+    let mut base: u64 = 10;
     while rhs/base != 0 {
-        base *= 10;
+        base = base.checked_mul(10)?;
     }
-    return lhs*base + rhs;
+    return Some(base);
+}
+
+fn concat_u64(lhs: u64, rhs: u64) -> Option<u64> {
+    lhs.checked_mul(concat_base(rhs)?)?.checked_add(rhs)
 }
 
-fn apply_operations(operations: &[OP], operands: &[u64]) -> u64 {
+fn checked_power(lhs: u64, rhs: u64) -> Option<u64> {
+    match lhs {
+        // exponent might not fit in a u32
+        0 => Some(if rhs == 0 { 1 } else { 0 }),
+        1 => Some(1),
+        _ => lhs.checked_pow(u32::try_from(rhs).ok()?)
+    }
+}
+
+// None when the operation isn't defined for these operands or overflows
+fn apply_op(op: &OP, lhs: u64, rhs: u64) -> Option<u64> {
+    match op {
+        OP::ADD => lhs.checked_add(rhs),
+        OP::MULTIPLY => lhs.checked_mul(rhs),
+        OP::CONCAT => concat_u64(lhs, rhs),
+        OP::SUBTRACT => lhs.checked_sub(rhs),
+        OP::DIVIDE => {
+            if rhs != 0 && lhs.is_multiple_of(rhs) { Some(lhs / rhs) } else { None }
+        },
+        OP::XOR => Some(lhs ^ rhs),
+        OP::POWER => checked_power(lhs, rhs)
+    }
+}
+
+// operators are applied left to right, regardless of precedence
+fn apply_operations(operations: &[OP], operands: &[u64]) -> Option<u64> {
     // put operations and operands in a queue
     let mut ops_queue: VecDeque<OP>= VecDeque::from(Vec::from(operations));
     let mut num_queue: VecDeque<u64> = VecDeque::from(Vec::from(operands));
@@ -386,11 +1243,7 @@ fn apply_operations(operations: &[OP], operands: &[u64]) -> u64 {
         if let Some(op) = ops_queue.pop_front() {
             let lhs = num_queue.pop_front().unwrap();
             let rhs = num_queue.pop_front().unwrap();
-            let computed = match op {
-                OP::ADD => lhs+rhs,
-                OP::MULTIPLY => lhs*rhs,
-                OP::CONCAT => concat_u64(lhs, rhs)
-            };
+            let computed = apply_op(&op, lhs, rhs)?;
             num_queue.push_front(computed);
         } else {
             break;
@@ -400,16 +1253,837 @@ fn apply_operations(operations: &[OP], operands: &[u64]) -> u64 {
     if num_queue.len() != 1 {
         panic!("Oops, bug or mismatch in operators/operands")
     }
-    return num_queue.pop_front().unwrap();
+    return num_queue.pop_front();
 }
 
-// returns the first valid match of operators
-pub fn find_ops(answer: u64, operands: &[u64], operators: &[OP]) -> Option<Vec<OP>> {
+// returns the first valid match of operators, trying every combination
+pub fn find_ops_brute_force(answer: u64, operands: &[u64], operators: &[OP]) -> Option<Vec<OP>> {
     let combos = OpsCombinatorial::new(&operators, (operands.len()-1) as u64);
     for combo in combos {
-        if answer == apply_operations(&combo, operands) {
+        if Some(answer) == apply_operations(&combo, operands) {
             return Some(combo);
         }
     }
     return None;
 }
+
+// what the operands before the last one have to evaluate to
+enum Prev {
+    Exact(u64),
+    Any,
+}
+
+fn integer_root(value: u64, n: u64) -> Option<u64> {
+    let exp = u32::try_from(n).unwrap_or(u32::MAX);
+    let guess = (value as f64).powf(1.0 / n as f64).round() as u64;
+    (guess.saturating_sub(1)..=guess+1).find(|&candidate| checked_power(candidate, exp as u64) == Some(value))
+}
+
+// undoes the last operation, None if `op` can't produce target from `last`
+fn undo_operation(op: &OP, target: u64, last: u64) -> Option<Prev> {
+    match op {
+        OP::ADD => target.checked_sub(last).map(Prev::Exact),
+        OP::MULTIPLY => {
+            if last == 0 {
+                if target == 0 { Some(Prev::Any) } else { None }
+            } else if target.is_multiple_of(last) {
+                Some(Prev::Exact(target / last))
+            } else {
+                None
+            }
+        },
+        OP::CONCAT => {
+            let base = concat_base(last)?;
+            if target >= last && target % base == last { Some(Prev::Exact(target / base)) } else { None }
+        },
+        OP::SUBTRACT => target.checked_add(last).map(Prev::Exact),
+        OP::DIVIDE => {
+            if last == 0 { None } else { target.checked_mul(last).map(Prev::Exact) }
+        },
+        OP::XOR => Some(Prev::Exact(target ^ last)),
+        OP::POWER => {
+            if last == 0 {
+                if target == 1 { Some(Prev::Any) } else { None }
+            } else {
+                integer_root(target, last).map(Prev::Exact)
+            }
+        }
+    }
+}
+
+// works from the last operand back to the first, ops holds the operators picked so far in reverse order
+fn collect_ops_backward(target: u64, operands: &[u64], operators: &[OP], ops: &mut Vec<OP>, found: &mut Vec<Vec<OP>>, limit: usize) {
+    let (&last, rest) = operands.split_last().unwrap();
+    if rest.is_empty() {
+        if target == last {
+            found.push(ops.iter().rev().cloned().collect());
+        }
+        return;
+    }
+    for op in operators {
+        if found.len() >= limit {
+            return;
+        }
+        match undo_operation(op, target, last) {
+            Some(Prev::Exact(prev_target)) => {
+                ops.push(op.clone());
+                collect_ops_backward(prev_target, rest, operators, ops, found, limit);
+                ops.pop();
+            },
+            Some(Prev::Any) => {
+                // the rest can be anything as long as it can be evaluated
+                for mut combo in OpsCombinatorial::new(operators, (rest.len()-1) as u64) {
+                    if found.len() >= limit {
+                        return;
+                    }
+                    if apply_operations(&combo, rest).is_some() {
+                        combo.push(op.clone());
+                        combo.extend(ops.iter().rev().cloned());
+                        found.push(combo);
+                    }
+                }
+            },
+            None => {}
+        }
+    }
+}
+
+// returns a valid match of operators
+pub fn find_ops(answer: u64, operands: &[u64], operators: &[OP]) -> Option<Vec<OP>> {
+    let mut found = Vec::with_capacity(1);
+    collect_ops_backward(answer, operands, operators, &mut Vec::new(), &mut found, 1);
+    found.pop()
+}
+
+// returns every valid match of operators
+pub fn find_all_ops(answer: u64, operands: &[u64], operators: &[OP]) -> Vec<Vec<OP>> {
+    let mut found = Vec::new();
+    collect_ops_backward(answer, operands, operators, &mut Vec::new(), &mut found, usize::MAX);
+    found
+}
+
+// e.g. `3267 = 81 + 40 * 27`, evaluated left to right
+pub fn format_equation(answer: u64, operands: &[u64], operators: &[OP]) -> String {
+    let mut ret = format!("{} = {}", answer, operands[0]);
+    for (op, operand) in operators.iter().zip(&operands[1..]) {
+        ret.push_str(&format!(" {} {}", op, operand));
+    }
+    ret
+}
+
+
+// undirected graph with interned node names, adjacency kept as sorted lists of ids
+#[derive(Debug, Default, Clone)]
+pub struct Graph {
+    names: Vec<String>,
+    ids: HashMap<String, usize>,
+    adjacency: Vec<Vec<usize>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DegreeStats {
+    pub min: usize,
+    pub max: usize,
+    pub mean: f64,
+}
+
+impl Graph {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // parses one `a-b` edge per line
+    pub fn parse_edges(text: &str, separator: &str) -> Self {
+        let mut graph = Self::new();
+        for line in text.trim().split('\n') {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (a, b) = line.split_once(separator).expect("Invalid edge");
+            graph.add_edge(a, b);
+        }
+        graph
+    }
+
+    pub fn intern(&mut self, name: &str) -> usize {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+        let id = self.names.len();
+        self.names.push(name.to_string());
+        self.ids.insert(name.to_string(), id);
+        self.adjacency.push(Vec::new());
+        id
+    }
+
+    pub fn add_edge(&mut self, a: &str, b: &str) {
+        let (u, v) = (self.intern(a), self.intern(b));
+        if u == v {
+            return;
+        }
+        for (from, to) in [(u, v), (v, u)] {
+            let list = &mut self.adjacency[from];
+            if let Err(pos) = list.binary_search(&to) {
+                list.insert(pos, to);
+            }
+        }
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.adjacency.iter().map(|list| list.len()).sum::<usize>() / 2
+    }
+
+    pub fn id(&self, name: &str) -> Option<usize> {
+        self.ids.get(name).copied()
+    }
+
+    pub fn name(&self, id: usize) -> &str {
+        &self.names[id]
+    }
+
+    pub fn names(&self, ids: &[usize]) -> Vec<&str> {
+        ids.iter().map(|&id| self.name(id)).collect()
+    }
+
+    pub fn neighbours(&self, id: usize) -> &[usize] {
+        &self.adjacency[id]
+    }
+
+    pub fn has_edge(&self, a: usize, b: usize) -> bool {
+        self.adjacency[a].binary_search(&b).is_ok()
+    }
+
+    pub fn degree(&self, id: usize) -> usize {
+        self.adjacency[id].len()
+    }
+
+    pub fn degree_stats(&self) -> Option<DegreeStats> {
+        if self.adjacency.is_empty() {
+            return None;
+        }
+        let degrees = self.adjacency.iter().map(|list| list.len());
+        Some(DegreeStats {
+            min: degrees.clone().min().unwrap(),
+            max: degrees.clone().max().unwrap(),
+            mean: degrees.sum::<usize>() as f64 / self.adjacency.len() as f64,
+        })
+    }
+
+    // each triangle is reported once, with ids in increasing order
+    pub fn triangles(&self) -> Vec<[usize; 3]> {
+        let mut ret = Vec::new();
+        for (u, list) in self.adjacency.iter().enumerate() {
+            for &v in list.iter().filter(|&&v| v > u) {
+                for &w in self.adjacency[v].iter().filter(|&&w| w > v) {
+                    if self.has_edge(u, w) {
+                        ret.push([u, v, w]);
+                    }
+                }
+            }
+        }
+        ret
+    }
+
+    pub fn connected_components(&self) -> Vec<Vec<usize>> {
+        let mut visited = vec![false; self.node_count()];
+        let mut components = Vec::new();
+        for start in 0..self.node_count() {
+            if visited[start] {
+                continue;
+            }
+            visited[start] = true;
+            let mut component = Vec::new();
+            let mut stack = vec![start];
+            while let Some(node) = stack.pop() {
+                component.push(node);
+                for &next in &self.adjacency[node] {
+                    if !visited[next] {
+                        visited[next] = true;
+                        stack.push(next);
+                    }
+                }
+            }
+            component.sort();
+            components.push(component);
+        }
+        components
+    }
+
+    // Bron-Kerbosch with pivoting, every clique is sorted by id
+    pub fn maximal_cliques(&self) -> Vec<Vec<usize>> {
+        let mut cliques = Vec::new();
+        let candidates: Vec<usize> = (0..self.node_count()).collect();
+        self.bron_kerbosch(&mut Vec::new(), candidates, Vec::new(), &mut cliques);
+        cliques
+    }
+
+    fn bron_kerbosch(&self, clique: &mut Vec<usize>, mut candidates: Vec<usize>, mut excluded: Vec<usize>, cliques: &mut Vec<Vec<usize>>) {
+        if candidates.is_empty() {
+            if excluded.is_empty() {
+                let mut found = clique.clone();
+                found.sort();
+                cliques.push(found);
+            }
+            return;
+        }
+        // pick the pivot covering the most candidates so we branch as little as possible
+        let pivot = candidates.iter().chain(excluded.iter())
+            .copied()
+            .max_by_key(|&p| candidates.iter().filter(|&&c| self.has_edge(p, c)).count())
+            .unwrap();
+        let branches: Vec<usize> = candidates.iter().copied().filter(|&c| !self.has_edge(pivot, c)).collect();
+        for node in branches {
+            let next_candidates = candidates.iter().copied().filter(|&c| self.has_edge(node, c)).collect();
+            let next_excluded = excluded.iter().copied().filter(|&c| self.has_edge(node, c)).collect();
+            clique.push(node);
+            self.bron_kerbosch(clique, next_candidates, next_excluded, cliques);
+            clique.pop();
+            candidates.retain(|&c| c != node);
+            excluded.push(node);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compute_multiplication_sum, parse_mul_pairs, tokenize, Instruction, InstructionReader, InstructionSpec, Interpreter, Token};
+    use super::{find_words, findall_in_grid, findall_x_in_grid, match_template, parse_letter_grid, Orientation, Template, WordHit, DIRECTIONS};
+    use super::{check_reports, dampen, is_safe, parse_line_by_line, Direction, Failure, FailureKind, SafetyRules, SafetySummary};
+    use super::{apply_operations, concat_u64, find_all_ops, find_ops, find_ops_brute_force, format_equation, middle_of_correct_orders,
+        middle_of_corrected_orders, parse_page_order_pb, sort_update, analyze_page_rules, CorrectedMiddle, Graph, OpsCombinatorial, OrderCycle, OP};
+
+    // small deterministic pseudo-random numbers, to generate test cases
+    struct Lcg {
+        state: u64,
+    }
+
+    impl Lcg {
+        fn new(seed: u64) -> Self {
+            Lcg { state: seed }
+        }
+
+        // next value in 0..modulo
+        fn below(&mut self, modulo: u64) -> u64 {
+            self.state = self.state.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (self.state >> 33) % modulo
+        }
+    }
+
+    const PAGE_ORDER_SAMPLE: &str = r"47|53
+97|13
+97|61
+97|47
+75|29
+61|13
+75|53
+29|13
+97|29
+53|29
+61|53
+97|53
+61|29
+47|13
+75|47
+97|75
+47|61
+75|61
+47|29
+75|13
+53|13
+
+75,47,61,53,29
+97,61,53,29,13
+75,29,13
+75,97,47,61,53
+61,13,29
+97,13,75,29,47";
+
+    #[test]
+    fn test_page_order_sample() {
+        let (rules, printing_pages) = parse_page_order_pb(PAGE_ORDER_SAMPLE);
+        assert_eq!(middle_of_correct_orders(&printing_pages, &rules).iter().sum::<i32>(), 143);
+        let corrected = middle_of_corrected_orders(&printing_pages, &rules).unwrap();
+        assert_eq!(corrected.iter().map(|fixed| fixed.middle).collect::<Vec<_>>(), vec![47, 29, 47]);
+        assert!(corrected.iter().all(|fixed| fixed.unique));
+        let sorted = sort_update(&[97, 13, 75, 29, 47], &rules).unwrap();
+        assert_eq!(sorted.order, vec![97, 75, 47, 29, 13]);
+        assert!(sorted.unique);
+    }
+
+    #[test]
+    fn test_page_order_cycle() {
+        let (rules, _) = parse_page_order_pb("1|2\n2|3\n3|1\n4|1\n\n4,1,2,3");
+        assert_eq!(sort_update(&[4, 1, 2, 3], &rules), Err(OrderCycle { pages: vec![1, 2, 3] }));
+        assert!(middle_of_corrected_orders(&[vec![4, 3, 2, 1]], &rules).is_err());
+        // the cycle needs all three pages to show up
+        assert_eq!(sort_update(&[3, 1], &rules).unwrap().order, vec![3, 1]);
+    }
+
+    #[test]
+    fn test_page_rules_analysis_sample() {
+        let (rules, printing_pages) = parse_page_order_pb(PAGE_ORDER_SAMPLE);
+        let analysis = analyze_page_rules(&rules, &printing_pages);
+        // the sample lists every pair of a 7 page chain, an update needs the pairs with none of
+        // its pages in between
+        assert_eq!(analysis.redundant_rules.len(), 11);
+        assert!(!analysis.redundant_rules.contains(&(75, 29)));
+        assert!(!analysis.redundant_rules.contains(&(97, 75)));
+        assert!(analysis.redundant_rules.contains(&(97, 13)));
+        assert!(analysis.unconstrained_pages.is_empty());
+        assert!(analysis.updates.iter().all(|report| report.total_order && report.cycle.is_none()));
+        let swaps: Vec<usize> = analysis.updates.iter().map(|report| report.swaps.len()).collect();
+        assert_eq!(swaps, vec![0, 0, 0, 1, 1, 2]);
+        assert_eq!(analysis.updates[5].swaps, vec![(1, 4), (1, 2)]);
+    }
+
+    #[test]
+    fn test_page_rules_analysis() {
+        let (rules, printing_pages) = parse_page_order_pb("1|2\n2|3\n1|3\n5|6\n6|5\n\n3,2,1\n1,9,3\n5,6");
+        let analysis = analyze_page_rules(&rules, &printing_pages);
+        // 3,2,1 gets 1|3 through 2 but 1,9,3 needs it
+        assert!(analysis.redundant_rules.is_empty());
+        assert_eq!(analysis.updates[0].redundant_rules, vec![(1, 3)]);
+        assert!(analysis.updates[1].redundant_rules.is_empty());
+        assert_eq!(analysis.unconstrained_pages, vec![9]);
+        assert_eq!(analysis.updates[0].swaps, vec![(0, 2)]);
+        assert!(analysis.updates[0].total_order);
+        assert!(analysis.updates[1].correct);
+        assert!(!analysis.updates[1].total_order);
+        assert_eq!(analysis.updates[2].cycle, Some(vec![5, 6]));
+        let report = analysis.to_string();
+        assert!(report.contains("[3, 2, 1]: 1 swaps [(0, 2)]"));
+        assert!(report.contains("[1, 9, 3]: correct (several valid orders)"));
+        assert!(report.contains("[5, 6]: cycle [5, 6]"));
+    }
+
+    #[test]
+    fn test_page_rules_analysis_cyclic_rules() {
+        // every page comes before the next two, round the circle: no order holds for all 5
+        // but the rules are fine on 3 consecutive pages
+        let (rules, printing_pages) = parse_page_order_pb("1|2\n1|3\n2|3\n2|4\n3|4\n3|5\n4|5\n4|1\n5|1\n5|2\n\n1,2,3\n3,2,4\n1,2,3,4,5");
+        let analysis = analyze_page_rules(&rules, &printing_pages);
+        assert_eq!(analysis.redundant_rules, vec![(1, 3), (2, 4)]);
+        assert_eq!(analysis.updates[0].redundant_rules, vec![(1, 3)]);
+        assert_eq!(analysis.updates[1].redundant_rules, vec![(2, 4)]);
+        assert_eq!(analysis.updates[1].swaps, vec![(0, 1)]);
+        assert!(analysis.updates[2].cycle.is_some());
+        assert!(analysis.updates[2].redundant_rules.is_empty());
+    }
+
+    #[test]
+    fn test_page_rules_analysis_repeated_pages() {
+        let (rules, printing_pages) = parse_page_order_pb("1|2\n\n5,5\n2,1,2");
+        let analysis = analyze_page_rules(&rules, &printing_pages);
+        assert!(analysis.updates[0].correct);
+        assert!(analysis.updates[0].swaps.is_empty());
+        let report = &analysis.updates[1];
+        let mut update = report.update.clone();
+        for &(i, j) in &report.swaps {
+            update.swap(i, j);
+        }
+        assert_eq!(update, sort_update(&report.update, &rules).unwrap().order);
+    }
+
+    #[test]
+    fn test_page_order_ambiguous() {
+        let (rules, _) = parse_page_order_pb("1|2\n1|3\n\n3,2,1");
+        let sorted = sort_update(&[3, 2, 1], &rules).unwrap();
+        assert_eq!(sorted.order, vec![1, 3, 2]);
+        assert!(!sorted.unique);
+        assert_eq!(middle_of_corrected_orders(&[vec![3, 2, 1]], &rules), Ok(vec![CorrectedMiddle { middle: 3, unique: false }]));
+    }
+
+    #[test]
+    fn test_mul_sample() {
+        let text = r"xmul(2,4)%&mul[3,7]!@^do_not_mul(5,5)+mul(32,64]then(mul(11,8)mul(8,5))";
+        assert_eq!(compute_multiplication_sum(&parse_mul_pairs(text)), 161);
+        assert_eq!(Interpreter::part1().run(text.as_bytes()).unwrap(), 161);
+        let text = r"xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))";
+        let mut interpreter = Interpreter::part2();
+        assert_eq!(interpreter.run(text.as_bytes()).unwrap(), 48);
+        let counted: Vec<String> = interpreter.trace.iter()
+            .filter(|entry| entry.counted)
+            .map(|entry| entry.token.instruction.to_string())
+            .collect();
+        assert_eq!(counted, vec!["mul(2,4)", "mul(8,5)"]);
+        assert!(interpreter.explain().contains("mul(5,5)       ignored -> 8"));
+    }
+
+    #[test]
+    fn test_custom_instructions() {
+        let mut interpreter = Interpreter::part2();
+        interpreter.register("add", 2, Box::new(|machine, args| {
+            if machine.enabled {
+                machine.accumulator += (args[0] + args[1]) as i64;
+            }
+            machine.enabled
+        }));
+        interpreter.register("reset", 0, Box::new(|machine, _| {
+            machine.accumulator = 0;
+            true
+        }));
+        interpreter.register("sq", 1, Box::new(|machine, args| {
+            machine.accumulator += (args[0] * args[0]) as i64;
+            true
+        }));
+        let text = "mul(2,3)reset()add(1,2)don't()add(5,5)do()sq(4)add(1,2,3)";
+        assert_eq!(interpreter.run(text.as_bytes()).unwrap(), 19);
+        let last = interpreter.trace.last().unwrap();
+        assert_eq!(last.token.instruction, Instruction::CALL("sq".to_string(), vec![4]));
+        assert_eq!(last.token.offset, 42);
+    }
+
+    #[test]
+    fn test_tokenizer() {
+        let tokens = tokenize("mumul(2,3)don't()mul(1234,5)mul(12,345)mul(1,2,3)do(5)do()mul( 1,2)");
+        assert_eq!(tokens, vec![
+            Token { instruction: Instruction::MUL(2, 3), offset: 2 },
+            Token { instruction: Instruction::DONT, offset: 10 },
+            Token { instruction: Instruction::MUL(12, 345), offset: 28 },
+            Token { instruction: Instruction::DO, offset: 54 },
+        ]);
+        // a failed token can hide the start of the next one
+        let tokens = tokenize("mul(1,mul(2,3)dodo()");
+        assert_eq!(tokens, vec![
+            Token { instruction: Instruction::MUL(2, 3), offset: 6 },
+            Token { instruction: Instruction::DO, offset: 16 },
+        ]);
+    }
+
+    #[test]
+    fn test_tokenizer_buffer_boundaries() {
+        let text = r"xmul(2,4)&mul[3,7]!^don't()_mul(5,5)+mul(32,64](mul(11,8)undo()?mul(8,5))";
+        let expected = tokenize(text);
+        assert_eq!(expected.len(), 6);
+        for size in 1..=text.len() {
+            let tokens: Vec<Token> = InstructionReader::with_buffer_size(text.as_bytes(), size)
+                .collect::<Result<_, _>>()
+                .unwrap();
+            assert_eq!(tokens, expected, "buffer size {}", size);
+        }
+    }
+
+    #[test]
+    fn test_overlapping_instruction_names() {
+        // "aab" starts right after the failed "aa" start
+        let specs = vec![InstructionSpec::new("aab", 0)];
+        let tokens: Vec<Token> = InstructionReader::with_instructions("aaab()".as_bytes(), specs, 2)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(tokens, vec![Token { instruction: Instruction::CALL("aab".to_string(), vec![]), offset: 1 }]);
+    }
+
+    #[test]
+    fn test_restart_after_failed_match() {
+        let text = r"mumul(1,2)dodo()mul(1234,5)mul(12,3)don't(do()";
+        let tokens = tokenize(text);
+        let expected = vec![
+            Token { instruction: Instruction::MUL(1, 2), offset: 2 },
+            Token { instruction: Instruction::DO, offset: 12 },
+            Token { instruction: Instruction::MUL(12, 3), offset: 27 },
+            Token { instruction: Instruction::DO, offset: 42 },
+        ];
+        assert_eq!(tokens, expected);
+        // instructions that were not asked for are not reported
+        let muls: Vec<Token> = InstructionReader::with_instructions(text.as_bytes(), vec![InstructionSpec::new("mul", 2)], 3)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(muls.len(), 2);
+    }
+
+    const WORD_SEARCH_SAMPLE: &str = r"MMMSXXMASM
+MSAMXMSMSA
+AMXSXMAAMM
+MSAMASMSMX
+XMASAMXAMM
+XXAMMXXAMA
+SMSMSASXSS
+SAXAMASAAA
+MAMMMXMMMM
+MXMXAXMASX";
+
+    #[test]
+    fn test_word_search_templates() {
+        let grid = parse_letter_grid(WORD_SEARCH_SAMPLE);
+        // part 1 is a straight and a diagonal template, each turned every way
+        let straight = match_template(&grid, &Template::parse("XMAS", '.'), true);
+        let diagonal = match_template(&grid, &Template::parse("X... .M.. ..A. ...S", '.'), true);
+        assert_eq!(straight.len() + diagonal.len(), 18);
+        assert_eq!(findall_in_grid(&grid, "XMAS"), 18);
+        // part 2
+        let crosses = match_template(&grid, &Template::parse("M.S .A. M.S", '.'), true);
+        assert_eq!(crosses.len(), 9);
+        assert_eq!(findall_x_in_grid(&grid, "MAS"), 9);
+        assert_eq!(Template::cross("MAS"), Template::parse("M.M .A. S.S", '.'));
+    }
+
+    // checks every cell and direction one letter at a time
+    fn find_words_brute_force(grid: &[Vec<char>], words: &[&str]) -> Vec<(String, usize, usize, (i32, i32))> {
+        let mut ret = Vec::new();
+        for word in words {
+            for i in 0..grid.len() {
+                for j in 0..grid[i].len() {
+                    for (dx, dy) in DIRECTIONS {
+                        let found = word.chars().enumerate().all(|(k, c)| {
+                            let (y, x) = (i as i32 + dy * k as i32, j as i32 + dx * k as i32);
+                            y >= 0 && x >= 0 && grid.get(y as usize).and_then(|line| line.get(x as usize)) == Some(&c)
+                        });
+                        if found {
+                            ret.push((word.to_string(), i, j, (dx, dy)));
+                        }
+                    }
+                }
+            }
+        }
+        ret.sort();
+        ret
+    }
+
+    #[test]
+    fn test_find_words() {
+        let grid = parse_letter_grid(WORD_SEARCH_SAMPLE);
+        let words = ["XMAS", "SAM", "MA", "AMX", "MASAMX", "SS"];
+        let mut found: Vec<(String, usize, usize, (i32, i32))> = find_words(&grid, &words).into_iter()
+            .map(|hit| (hit.word, hit.row, hit.col, hit.direction))
+            .collect();
+        found.sort();
+        assert_eq!(found, find_words_brute_force(&grid, &words));
+        let hits = find_words(&parse_letter_grid("QXQ\nQQM\nQQQ"), &["XM"]);
+        assert_eq!(hits, vec![WordHit { word: "XM".to_string(), row: 0, col: 1, direction: (1, 1) }]);
+        // a 1 letter word reads the same in every direction
+        assert_eq!(find_words(&parse_letter_grid("AB"), &["A"]).len(), 8);
+        assert!(find_words(&grid, &["QQ"]).is_empty());
+        assert_eq!(findall_in_grid(&parse_letter_grid("XMAS\nX\nMX"), "XM"), 4);
+    }
+
+    #[test]
+    fn test_template_orientations() {
+        // a line only has 4 distinct ways to be laid down
+        let line = Template::parse("XMAS", '.');
+        let orientations = line.orientations();
+        assert_eq!(orientations.len(), 4);
+        assert_eq!(orientations[1].1, Template::parse("X M A S", '.'));
+        assert_eq!(line.rotate().rotate(), line.reflect());
+        assert_eq!(Template::parse("AB C.", '.').orientations().len(), 8);
+        let grid = parse_letter_grid("XS\nMA\nAM\nSX");
+        let found = match_template(&grid, &line, true);
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].col, 0);
+        assert_eq!(found[0].orientation, Orientation { rotation: 1, reflected: false });
+        assert_eq!(found[1].orientation, Orientation { rotation: 3, reflected: false });
+        assert!(match_template(&grid, &line, false).is_empty());
+        // narrow grids are fine
+        assert_eq!(findall_x_in_grid(&parse_letter_grid("M\nA"), "MAS"), 0);
+    }
+
+    const REPORTS_SAMPLE: &str = r"7 6 4 2 1
+1 2 7 8 9
+9 7 6 2 1
+1 3 2 4 5
+8 6 4 4 1
+1 3 6 7 9";
+
+    // safe after removing the given indexes?
+    fn safe_without(report: &[i32], removed: &[usize]) -> bool {
+        let kept: Vec<i32> = report.iter().enumerate()
+            .filter(|(idx, _)| !removed.contains(idx))
+            .map(|(_, &level)| level)
+            .collect();
+        is_safe(&kept, &SafetyRules::default())
+    }
+
+    #[test]
+    fn test_reports_sample() {
+        let reports = parse_line_by_line(REPORTS_SAMPLE);
+        let rules = SafetyRules::default();
+        let (_, summary) = check_reports(&reports, &rules, 0);
+        assert_eq!(summary, SafetySummary { safe: 2, dampened: 0, direction: 1, step: 2, equal: 1 });
+        let (results, summary) = check_reports(&reports, &rules, 1);
+        assert_eq!(summary, SafetySummary { safe: 4, dampened: 2, direction: 0, step: 2, equal: 0 });
+        assert_eq!(results[1].failure, Some(Failure { index: 1, kind: FailureKind::STEP }));
+        assert_eq!(results[3].failure, Some(Failure { index: 1, kind: FailureKind::DIRECTION }));
+        assert_eq!(results[4].failure, Some(Failure { index: 2, kind: FailureKind::EQUAL }));
+        // either of the 4s can go
+        assert_eq!(results[4].removed.len(), 1);
+        assert!(safe_without(&reports[4], &results[4].removed));
+        assert!(results[0].removed.is_empty());
+        // 1 7 and 2 7 are both too far apart
+        assert!(!results[1].safe);
+        assert!(dampen(&reports[1], 2, &rules).safe);
+        assert_eq!(dampen(&reports[1], 2, &rules).removed.len(), 2);
+    }
+
+    #[test]
+    fn test_custom_safety_rules() {
+        let reports = parse_line_by_line(REPORTS_SAMPLE);
+        let increasing = SafetyRules { direction: Direction::INCREASING, ..SafetyRules::default() };
+        assert!(!is_safe(&reports[0], &increasing));
+        assert!(is_safe(&reports[5], &increasing));
+        assert_eq!(check_reports(&reports, &increasing, 0).1.safe, 1);
+        let flat_ok = SafetyRules { allow_equal: true, ..SafetyRules::default() };
+        assert!(is_safe(&reports[4], &flat_ok));
+        assert!(is_safe(&[3, 3, 2, 2], &flat_ok));
+        assert!(!is_safe(&[3, 3, 4, 2], &flat_ok));
+        let wide = SafetyRules { min_step: 2, max_step: 5, ..SafetyRules::default() };
+        assert!(is_safe(&[2, 7, 9], &wide));
+        assert!(!is_safe(&[2, 7, 9], &SafetyRules::default()));
+        assert_eq!(dampen(&[1, 3, 4, 6], 1, &wide).removed, vec![2]);
+        assert_eq!(dampen(&[1, 3, 4, 6], 1, &wide).failure, Some(Failure { index: 1, kind: FailureKind::STEP }));
+    }
+
+    #[test]
+    fn test_dampen_matches_brute_force() {
+        let mut rng = Lcg::new(4242);
+        for _ in 0..2000 {
+            let len = 1 + rng.below(8) as usize;
+            let mut level = rng.below(20) as i32;
+            let report: Vec<i32> = (0..len).map(|_| {
+                level += rng.below(9) as i32 - 3;
+                level
+            }).collect();
+            for tolerance in 0..=2 {
+                let rules = SafetyRules::default();
+                let mut expected = is_safe(&report, &rules);
+                for i in 0..len {
+                    expected |= tolerance >= 1 && safe_without(&report, &[i]);
+                    for j in i+1..len {
+                        expected |= tolerance >= 2 && safe_without(&report, &[i, j]);
+                    }
+                }
+                let result = dampen(&report, tolerance, &rules);
+                assert_eq!(result.safe, expected, "{:?} with tolerance {}", report, tolerance);
+                assert_eq!(result.failure.is_none(), is_safe(&report, &rules));
+                if result.safe {
+                    assert!(result.removed.len() <= tolerance);
+                    assert!(safe_without(&report, &result.removed), "{:?} removing {:?}", report, result.removed);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_find_ops_sample() {
+        let ops_p1 = [OP::ADD, OP::MULTIPLY];
+        let ops_p2 = [OP::ADD, OP::MULTIPLY, OP::CONCAT];
+        assert!(find_ops(190, &[10, 19], &ops_p1).is_some());
+        assert!(find_ops(3267, &[81, 40, 27], &ops_p1).is_some());
+        assert!(find_ops(292, &[11, 6, 16, 20], &ops_p1).is_some());
+        assert!(find_ops(156, &[15, 6], &ops_p1).is_none());
+        assert!(find_ops(156, &[15, 6], &ops_p2).is_some());
+        assert!(find_ops(7290, &[6, 8, 6, 15], &ops_p2).is_some());
+        assert!(find_ops(192, &[17, 8, 14], &ops_p2).is_some());
+        assert!(find_ops(21037, &[9, 7, 18, 13], &ops_p2).is_none());
+        assert!(find_ops(0, &[5, 3, 0], &ops_p1).is_some());
+    }
+
+    #[test]
+    fn test_find_ops_matches_brute_force() {
+        let ops = [OP::ADD, OP::MULTIPLY, OP::CONCAT];
+        // small deterministic pseudo-random equations
+        let mut rng = Lcg::new(12345);
+        for _ in 0..500 {
+            let count = 2 + rng.below(4) as usize;
+            let operands: Vec<u64> = (0..count).map(|_| rng.below(20)).collect();
+            let answer = if rng.below(2) == 0 { rng.below(5000) } else {
+                let combo: Vec<OP> = (1..count).map(|_| ops[rng.below(3) as usize].clone()).collect();
+                apply_operations(&combo, &operands).unwrap()
+            };
+            let expected = find_ops_brute_force(answer, &operands, &ops);
+            let found = find_ops(answer, &operands, &ops);
+            assert_eq!(found.is_some(), expected.is_some(), "{}: {:?}", answer, operands);
+            if let Some(combo) = found {
+                assert_eq!(apply_operations(&combo, &operands), Some(answer));
+            }
+        }
+    }
+
+    #[test]
+    fn test_find_all_ops_matches_brute_force() {
+        let ops = [OP::ADD, OP::MULTIPLY, OP::CONCAT, OP::SUBTRACT, OP::DIVIDE, OP::XOR, OP::POWER];
+        let mut rng = Lcg::new(777);
+        for _ in 0..300 {
+            let count = 2 + rng.below(3) as usize;
+            let operands: Vec<u64> = (0..count).map(|_| rng.below(6)).collect();
+            let answer = rng.below(40);
+            let mut expected: Vec<String> = OpsCombinatorial::new(&ops, (count-1) as u64)
+                .filter(|combo| apply_operations(combo, &operands) == Some(answer))
+                .map(|combo| format_equation(answer, &operands, &combo))
+                .collect();
+            let mut found: Vec<String> = find_all_ops(answer, &operands, &ops).iter()
+                .map(|combo| format_equation(answer, &operands, combo))
+                .collect();
+            expected.sort();
+            found.sort();
+            assert_eq!(found, expected, "{}: {:?}", answer, operands);
+        }
+    }
+
+    #[test]
+    fn test_new_operators() {
+        assert_eq!(find_ops(2, &[10, 8], &[OP::ADD, OP::SUBTRACT]), Some(vec![OP::SUBTRACT]));
+        assert_eq!(find_ops(5, &[2, 8], &[OP::SUBTRACT]), None);
+        assert_eq!(find_ops(4, &[20, 5], &[OP::DIVIDE]), Some(vec![OP::DIVIDE]));
+        assert_eq!(find_ops(3, &[20, 6], &[OP::DIVIDE]), None);
+        assert_eq!(find_ops(6, &[5, 3], &[OP::XOR]), Some(vec![OP::XOR]));
+        assert_eq!(find_ops(81, &[3, 2, 2], &[OP::POWER]), Some(vec![OP::POWER, OP::POWER]));
+        assert_eq!(find_ops(1, &[7, 4, 0], &[OP::ADD, OP::POWER]).map(|ops| ops.len()), Some(2));
+    }
+
+    #[test]
+    fn test_ops_near_u64_max() {
+        let ops = [OP::ADD, OP::MULTIPLY, OP::CONCAT];
+        assert_eq!(concat_u64(u64::MAX / 10, 5), Some(u64::MAX));
+        assert_eq!(concat_u64(u64::MAX / 10, 6), None);
+        assert_eq!(concat_u64(1, u64::MAX), None);
+        assert_eq!(apply_operations(&[OP::MULTIPLY], &[u64::MAX, 2]), None);
+        assert_eq!(apply_operations(&[OP::ADD], &[u64::MAX, 1]), None);
+        assert_eq!(find_ops(u64::MAX, &[u64::MAX - 1, 1], &ops), Some(vec![OP::ADD]));
+        assert_eq!(find_ops(u64::MAX, &[u64::MAX / 10, 5], &ops), Some(vec![OP::CONCAT]));
+        assert_eq!(find_ops(u64::MAX - 1, &[(u64::MAX - 1) / 2, 2], &ops), Some(vec![OP::MULTIPLY]));
+        // 2^63 * 2 wraps around to 0, it must not count as a match
+        assert_eq!(find_ops(0, &[1 << 63, 2], &ops), None);
+        assert_eq!(find_ops_brute_force(0, &[1 << 63, 2], &ops), None);
+        assert_eq!(find_ops_brute_force(1, &[u64::MAX, 2, 1], &ops), None);
+        assert_eq!(find_ops(u64::MAX, &[u64::MAX, 10, 5], &ops), None);
+    }
+
+    #[test]
+    fn test_format_equation() {
+        let ops = [OP::ADD, OP::MULTIPLY];
+        let all = find_all_ops(3267, &[81, 40, 27], &ops);
+        let mut equations: Vec<String> = all.iter().map(|combo| format_equation(3267, &[81, 40, 27], combo)).collect();
+        equations.sort();
+        assert_eq!(equations, vec!["3267 = 81 * 40 + 27", "3267 = 81 + 40 * 27"]);
+        assert_eq!(format_equation(156, &[15, 6], &[OP::CONCAT]), "156 = 15 || 6");
+    }
+
+    #[test]
+    fn test_graph_basics() {
+        // two triangles sharing an edge, plus a separate pair
+        let graph = Graph::parse_edges("a-b\nb-c\nc-a\nc-d\nd-b\nx-y\n", "-");
+        assert_eq!(graph.node_count(), 6);
+        assert_eq!(graph.edge_count(), 6);
+        let (b, c) = (graph.id("b").unwrap(), graph.id("c").unwrap());
+        assert!(graph.has_edge(b, c));
+        assert_eq!(graph.degree(b), 3);
+        let stats = graph.degree_stats().unwrap();
+        assert_eq!((stats.min, stats.max), (1, 3));
+        assert_eq!(stats.mean, 2.0);
+        assert_eq!(graph.triangles().len(), 2);
+        let mut components: Vec<Vec<&str>> = graph.connected_components().iter()
+            .map(|component| graph.names(component))
+            .collect();
+        components.sort();
+        assert_eq!(components, vec![vec!["a", "b", "c", "d"], vec!["x", "y"]]);
+        let mut cliques: Vec<Vec<&str>> = graph.maximal_cliques().iter()
+            .map(|clique| graph.names(clique))
+            .collect();
+        cliques.sort();
+        assert_eq!(cliques, vec![vec!["a", "b", "c"], vec!["b", "c", "d"], vec!["x", "y"]]);
+    }
+}